@@ -0,0 +1,83 @@
+//! Benchmarks of the binding evaluation, run with `cargo bench --no-default-features --features std`
+//!
+//! Each benchmark is run with the pool of dependency nodes disabled and enabled, to compare
+//! with one allocation per dependency.
+
+use propertybindings::properties_impl::{set_node_pool_capacity, Property};
+use std::pin::Pin;
+use std::time::Instant;
+
+fn measure(name: &str, iterations: u32, f: &mut dyn FnMut()) {
+    let mut run = || {
+        let start = Instant::now();
+        for _ in 0..iterations {
+            f();
+        }
+        start.elapsed() / iterations
+    };
+    set_node_pool_capacity(0);
+    run(); // warm up
+    let without_pool = run();
+    set_node_pool_capacity(1024);
+    run();
+    let with_pool = run();
+    println!(
+        "{:<16} without pool: {:>10?}   with pool: {:>10?}",
+        name, without_pool, with_pool
+    );
+}
+
+fn properties(count: usize) -> Vec<Pin<Box<Property<u64>>>> {
+    (0..count).map(|_| Box::pin(Property::default())).collect()
+}
+
+/// Each property depends on the previous one
+fn chain() {
+    let props = properties(100);
+    for w in props.windows(2) {
+        let prev = w[0].as_ref();
+        w[1].as_ref().set_binding_owned(move || prev.get() + 1);
+    }
+    let mut i = 0;
+    measure("chain", 10000, &mut || {
+        i += 1;
+        props[0].as_ref().set(i);
+        assert_eq!(props[99].as_ref().get(), i + 99);
+    });
+}
+
+/// One property depends on all the others, like a layout
+fn fan_in() {
+    let props = properties(100);
+    let sum = Box::pin(Property::default());
+    let p = &props;
+    sum.as_ref()
+        .set_binding_owned(move || p.iter().map(|x| x.as_ref().get()).sum::<u64>());
+    let mut i = 0;
+    measure("fan_in", 10000, &mut || {
+        i += 1;
+        props[0].as_ref().set(i);
+        assert_eq!(sum.as_ref().get(), i);
+    });
+}
+
+/// A binding which reads the same property several times
+fn repeated_reads() {
+    let a = Box::pin(Property::default());
+    let b = Box::pin(Property::default());
+    let a_ref = a.as_ref();
+    b.as_ref()
+        .set_binding_owned(move || (0..20).map(|_| a_ref.get()).sum::<u64>());
+    let mut i = 0;
+    measure("repeated_reads", 100000, &mut || {
+        i += 1;
+        a.as_ref().set(i);
+        assert_eq!(b.as_ref().get(), i * 20);
+    });
+}
+
+fn main() {
+    chain();
+    fan_in();
+    repeated_reads();
+}
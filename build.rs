@@ -15,9 +15,12 @@ NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FO
 OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
 CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
 */
+#[cfg(feature = "qt")]
 extern crate cpp_build;
+#[cfg(feature = "qt")]
 use std::process::Command;
 
+#[cfg(feature = "qt")]
 fn qmake_query(var: &str) -> String {
     String::from_utf8(
         Command::new("qmake")
@@ -30,6 +33,13 @@ fn qmake_query(var: &str) -> String {
 }
 
 fn main() {
+    // Only the Qt items need to be compiled and linked with Qt
+    #[cfg(feature = "qt")]
+    build_qt();
+}
+
+#[cfg(feature = "qt")]
+fn build_qt() {
     let qt_include_path = qmake_query("QT_INSTALL_HEADERS");
     let qt_library_path = qmake_query("QT_INSTALL_LIBS");
 
@@ -5,32 +5,33 @@
 #[macro_use]
 extern crate propertybindings;
 
+use std::pin::Pin;
 use std::rc::Rc;
 
-use propertybindings::properties::Property;
+use propertybindings::properties::RcProperty;
 
 #[derive(Default)]
 struct PlusMinus {
-    counter: Property<'static, i32>,
+    counter: RcProperty<'static, i32>,
 }
 
 impl propertybindings::quick::ItemFactory for PlusMinus {
-    fn create() -> Rc<dyn propertybindings::items::Item<'static>> {
+    fn create() -> Pin<Rc<dyn propertybindings::items::Item<'static>>> {
         use propertybindings::items::*;
-        use qmetaobject::{QColor, QString};
+        use qmetaobject::{QColor, QPointF, QString};
 
         rsml! { struct Button : Container {
-            @signal on_clicked,
+            @signal on_clicked(pos: QPointF, button: MouseButton),
             text: QString;
-            Rectangle { color: QColor::from_name(if mouse.pressed.get() {"#aaa"} else {"#ccc"} ) }
+            Rectangle { color: QColor::from_name(if mouse.pressed().get() {"#aaa"} else {"#ccc"} ) }
             Text {
-                text: Button.text.get(),
+                text: Button.text().get(),
                 vertical_alignment: alignment::VCENTER,
                 horizontal_alignment: alignment::HCENTER,
             }
             MouseArea {
                 @id: mouse,
-                on_clicked: Button.on_clicked.emit()
+                on_clicked: |pos, button| Button.on_clicked().emit((pos, button))
             }
         }}
 
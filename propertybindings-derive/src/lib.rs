@@ -0,0 +1,182 @@
+//! The `#[derive(Properties)]` macro, re-exported by `propertybindings::properties_impl`
+//! when the `derive` feature is enabled. See the documentation there.
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote, quote_spanned};
+use syn::spanned::Spanned;
+use syn::{
+    parse_macro_input, Data, DeriveInput, Fields, GenericArgument, GenericParam, Lifetime,
+    LifetimeDef, PathArguments, Type,
+};
+
+#[proc_macro_derive(Properties)]
+pub fn derive_properties(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match derive(&input) {
+        Ok(t) => t.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+/// Returns T if the type is `Property<T>`
+fn property_type(ty: &Type) -> Option<&Type> {
+    let path = match ty {
+        Type::Path(p) if p.qself.is_none() => &p.path,
+        _ => return None,
+    };
+    let last = path.segments.last()?;
+    if last.ident != "Property" {
+        return None;
+    }
+    match &last.arguments {
+        PathArguments::AngleBracketed(args) if args.args.len() == 1 => match &args.args[0] {
+            GenericArgument::Type(t) => Some(t),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// A lifetime named after `base` which is not one of the struct's
+fn fresh_lifetime(input: &DeriveInput, base: &str) -> Lifetime {
+    let mut name = format!("'__{}", base);
+    while input
+        .generics
+        .lifetimes()
+        .any(|l| l.lifetime.ident == name[1..])
+    {
+        name.push('_');
+    }
+    Lifetime::new(&name, Span::call_site())
+}
+
+fn derive(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let fields = match &input.data {
+        Data::Struct(s) => match &s.fields {
+            Fields::Named(f) => &f.named,
+            _ => {
+                return Err(syn::Error::new(
+                    input.ident.span(),
+                    "Properties can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new(
+                input.ident.span(),
+                "Properties can only be derived for structs",
+            ))
+        }
+    };
+    // The fields of a packed struct may be moved
+    for attr in &input.attrs {
+        if attr.path.is_ident("repr") && attr.tokens.to_string().contains("packed") {
+            return Err(syn::Error::new(
+                attr.span(),
+                "Properties cannot be derived for packed structs",
+            ));
+        }
+    }
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let pin = quote!(::core::pin::Pin);
+    let krate = quote!(::propertybindings::properties_impl);
+    let binding_lifetime = fresh_lifetime(input, "binding");
+
+    let mut accessors = Vec::new();
+    let mut pinned_types = Vec::new();
+    for field in fields {
+        let ty = match property_type(&field.ty) {
+            Some(ty) => ty,
+            None => continue,
+        };
+        let field_ty = &field.ty;
+        pinned_types.push(field_ty);
+        let ident = field.ident.as_ref().unwrap();
+        let vis = &field.vis;
+        let getter = format_ident!("get_{}", ident);
+        let setter = format_ident!("set_{}", ident);
+        let bind = format_ident!("set_{}_binding", ident);
+        accessors.push(quote_spanned! {field.span()=>
+            #vis fn #ident(self: #pin<&Self>) -> #pin<&#field_ty> {
+                // Safety: the struct is not Unpin because its properties are not, and it cannot
+                // implement Unpin nor Drop, see the guards below. It is not packed, so the
+                // fields are never moved.
+                unsafe { #pin::map_unchecked(self, |s| &s.#ident) }
+            }
+            // The bound is higher-ranked so that it is not an error when the type is not Clone
+            #vis fn #getter(self: #pin<&Self>) -> #ty
+            where
+                for<'__clone> #ty: ::core::clone::Clone,
+            {
+                self.#ident().get()
+            }
+            #vis fn #setter(self: #pin<&Self>, value: #ty) {
+                self.#ident().set(value)
+            }
+            #vis fn #bind<#binding_lifetime>(
+                self: #pin<&#binding_lifetime Self>,
+                binding: #pin<
+                    &#binding_lifetime #krate::BindingStorage<
+                        dyn #krate::Binding<#ty> + #binding_lifetime,
+                    >,
+                >,
+            ) {
+                self.#ident().set_binding(binding)
+            }
+        });
+    }
+
+    // Same trick as pin-project: this conflicts with the blanket impl if the struct
+    // implements Drop, which could move the properties out of the pinned struct.
+    let must_not_impl_drop = format_ident!("{}MustNotImplDrop", name);
+    let guard = quote_spanned! {Span::call_site()=>
+        #[allow(non_camel_case_types, dead_code)]
+        trait #must_not_impl_drop {}
+        #[allow(drop_bounds)]
+        impl<T: ::core::ops::Drop> #must_not_impl_drop for T {}
+        impl #impl_generics #must_not_impl_drop for #name #ty_generics #where_clause {}
+    };
+
+    // Also from pin-project: the struct is only Unpin if its properties are, which they never
+    // are. An impl of Unpin written by the user conflicts with this one.
+    let pin_lifetime = fresh_lifetime(input, "pin");
+    let mut origin_generics = input.generics.clone();
+    origin_generics.params.insert(
+        0,
+        GenericParam::Lifetime(LifetimeDef::new(pin_lifetime.clone())),
+    );
+    let (origin_impl_generics, origin_ty_generics, origin_where_clause) =
+        origin_generics.split_for_impl();
+    let origin = format_ident!("__{}PinnedFields", name);
+    let predicates = input.generics.where_clause.as_ref().map(|w| &w.predicates);
+    let unpin_guard = quote_spanned! {Span::call_site()=>
+        #[allow(non_camel_case_types, dead_code)]
+        struct #origin #origin_impl_generics #origin_where_clause {
+            __pin: ::core::marker::PhantomData<&#pin_lifetime ()>,
+            __struct: ::core::marker::PhantomData<fn() -> #name #ty_generics>,
+            __fields: (#(#pinned_types,)*),
+        }
+        impl #origin_impl_generics ::core::marker::Unpin for #name #ty_generics
+        where
+            #origin #origin_ty_generics: ::core::marker::Unpin,
+            #predicates
+        {
+        }
+    };
+
+    Ok(quote! {
+        #[allow(dead_code)]
+        impl #impl_generics #name #ty_generics #where_clause {
+            #(#accessors)*
+        }
+        const _: () = {
+            #guard
+            #unpin_guard
+        };
+    })
+}
@@ -1,6 +1,7 @@
-use super::items::Geometry;
+use super::layout::Geometry;
 use super::{Property, PropertyBindingFn};
 use std::marker::PhantomData;
+use std::pin::Pin;
 pub enum BeginTag {}
 pub enum EndTag {}
 pub enum CenterTag {}
@@ -57,20 +58,20 @@ macro_rules! declare_AnchorCanAdd {
 declare_AnchorCanAdd! {[@ BeginTag EndTag CenterTag SizeTag]}
 
 pub trait AnchorApplyGeometry<'a> {
-    fn apply_geometry(self, begin: &Property<'a, f64>, size: &Property<'a, f64>);
+    fn apply_geometry(self, begin: Pin<&Property<'a, f64>>, size: Pin<&Property<'a, f64>>);
 }
 impl<'a> AnchorApplyGeometry<'a> for () {
-    fn apply_geometry(self, _begin: &Property<'a, f64>, _size: &Property<'a, f64>) {}
+    fn apply_geometry(self, _begin: Pin<&Property<'a, f64>>, _size: Pin<&Property<'a, f64>>) {}
 }
 impl<'a, F: PropertyBindingFn<f64> + 'a> AnchorApplyGeometry<'a>
     for AnchorElement<'a, BeginTag, F>
 {
-    fn apply_geometry(self, begin: &Property<'a, f64>, _size: &Property<'a, f64>) {
+    fn apply_geometry(self, begin: Pin<&Property<'a, f64>>, _size: Pin<&Property<'a, f64>>) {
         begin.set_binding(self.f);
     }
 }
 impl<'a, F: PropertyBindingFn<f64> + 'a> AnchorApplyGeometry<'a> for AnchorElement<'a, EndTag, F> {
-    fn apply_geometry(self, begin: &Property<'a, f64>, size: &Property<'a, f64>) {
+    fn apply_geometry(self, begin: Pin<&Property<'a, f64>>, size: Pin<&Property<'a, f64>>) {
         let ws = size.as_weak();
         begin.set_binding(Some(move || Some(self.f.run()? - ws.get()?)));
     }
@@ -78,13 +79,13 @@ impl<'a, F: PropertyBindingFn<f64> + 'a> AnchorApplyGeometry<'a> for AnchorEleme
 impl<'a, F: PropertyBindingFn<f64> + 'a> AnchorApplyGeometry<'a>
     for AnchorElement<'a, CenterTag, F>
 {
-    fn apply_geometry(self, begin: &Property<'a, f64>, size: &Property<'a, f64>) {
+    fn apply_geometry(self, begin: Pin<&Property<'a, f64>>, size: Pin<&Property<'a, f64>>) {
         let ws = size.as_weak();
         begin.set_binding(Some(move || Some(self.f.run()? - ws.get()? / 2.)));
     }
 }
 impl<'a, F: PropertyBindingFn<f64> + 'a> AnchorApplyGeometry<'a> for AnchorElement<'a, SizeTag, F> {
-    fn apply_geometry(self, _begin: &Property<'a, f64>, size: &Property<'a, f64>) {
+    fn apply_geometry(self, _begin: Pin<&Property<'a, f64>>, size: Pin<&Property<'a, f64>>) {
         size.set_binding(self.f);
     }
 }
@@ -94,7 +95,7 @@ impl<'a, F1: PropertyBindingFn<f64> + 'a, F2: PropertyBindingFn<f64> + 'a> Ancho
         AnchorElement<'a, EndTag, F2>,
     )
 {
-    fn apply_geometry(self, begin: &Property<'a, f64>, size: &Property<'a, f64>) {
+    fn apply_geometry(self, begin: Pin<&Property<'a, f64>>, size: Pin<&Property<'a, f64>>) {
         begin.set_binding((self.0).f);
         let end = self.1;
         let ws = begin.as_weak();
@@ -107,7 +108,7 @@ impl<'a, F1: PropertyBindingFn<f64> + 'a, F2: PropertyBindingFn<f64> + 'a> Ancho
         AnchorElement<'a, CenterTag, F2>,
     )
 {
-    fn apply_geometry(self, begin: &Property<'a, f64>, size: &Property<'a, f64>) {
+    fn apply_geometry(self, begin: Pin<&Property<'a, f64>>, size: Pin<&Property<'a, f64>>) {
         begin.set_binding((self.0).f);
         let center = self.1;
         let ws = begin.as_weak();
@@ -120,7 +121,7 @@ impl<'a, F1: PropertyBindingFn<f64> + 'a, F2: PropertyBindingFn<f64> + 'a> Ancho
         AnchorElement<'a, SizeTag, F2>,
     )
 {
-    fn apply_geometry(self, begin: &Property<'a, f64>, size: &Property<'a, f64>) {
+    fn apply_geometry(self, begin: Pin<&Property<'a, f64>>, size: Pin<&Property<'a, f64>>) {
         begin.set_binding((self.0).f);
         size.set_binding((self.1).f);
     }
@@ -132,7 +133,7 @@ impl<'a, F1: PropertyBindingFn<f64> + Clone + 'a, F2: PropertyBindingFn<f64> + C
         AnchorElement<'a, CenterTag, F2>,
     )
 {
-    fn apply_geometry(self, begin: &Property<'a, f64>, size: &Property<'a, f64>) {
+    fn apply_geometry(self, begin: Pin<&Property<'a, f64>>, size: Pin<&Property<'a, f64>>) {
         let center = (self.1).f.clone();
         let end = (self.0).f.clone();
         begin.set_binding(Some(move || {
@@ -147,7 +148,7 @@ impl<'a, F1: PropertyBindingFn<f64> + 'a, F2: PropertyBindingFn<f64> + 'a> Ancho
         AnchorElement<'a, SizeTag, F2>,
     )
 {
-    fn apply_geometry(self, begin: &Property<'a, f64>, size: &Property<'a, f64>) {
+    fn apply_geometry(self, begin: Pin<&Property<'a, f64>>, size: Pin<&Property<'a, f64>>) {
         size.set_binding((self.1).f);
         let ws = size.as_weak();
         let end = self.0;
@@ -160,7 +161,7 @@ impl<'a, F1: PropertyBindingFn<f64> + 'a, F2: PropertyBindingFn<f64> + 'a> Ancho
         AnchorElement<'a, SizeTag, F2>,
     )
 {
-    fn apply_geometry(self, begin: &Property<'a, f64>, size: &Property<'a, f64>) {
+    fn apply_geometry(self, begin: Pin<&Property<'a, f64>>, size: Pin<&Property<'a, f64>>) {
         size.set_binding((self.1).f);
         let center = self.0;
         let ws = size.as_weak();
@@ -190,13 +191,13 @@ impl<Horiz, Vert> Anchor<Horiz, Vert> {
     declare_AnchorFunc! {horizontal_center, vertical_center, CenterTag}
     declare_AnchorFunc! {width, height, SizeTag}
 
-    pub fn apply_geometry<'a>(self, g: &Geometry<'a>)
+    pub fn apply_geometry<'a>(self, g: Pin<&Geometry<'a>>)
     where
         Horiz: AnchorApplyGeometry<'a>,
         Vert: AnchorApplyGeometry<'a>,
     {
-        self.h.apply_geometry(&g.x, &g.width);
-        self.v.apply_geometry(&g.y, &g.height);
+        self.h.apply_geometry(g.x(), g.width());
+        self.v.apply_geometry(g.y(), g.height());
     }
 }
 pub fn new_anchor() -> Anchor<(), ()> {
@@ -207,10 +208,12 @@ pub fn new_anchor() -> Anchor<(), ()> {
 fn test_anchor() {
     {
         let a = new_anchor().left(|| 78.).bottom(|| 52.);
-        let g = Geometry::default();
-        a.apply_geometry(&g);
-        g.width.set(12.);
-        g.height.set(11.);
+        let owner = std::rc::Rc::pin(Geometry::default());
+        let g = owner.as_ref();
+        g.set_owner(&owner);
+        a.apply_geometry(g);
+        g.width().set(12.);
+        g.height().set(11.);
         assert_eq!(g.left(), 78.);
         assert_eq!(g.right(), 78. + 12.);
         assert_eq!(g.top(), 52. - 11.);
@@ -223,8 +226,10 @@ fn test_anchor() {
             .bottom(|| 52.)
             .width(|| 12.)
             .height(|| 11.);
-        let g = Geometry::default();
-        a.apply_geometry(&g);
+        let owner = std::rc::Rc::pin(Geometry::default());
+        let g = owner.as_ref();
+        g.set_owner(&owner);
+        a.apply_geometry(g);
         assert_eq!(g.left(), 78.);
         assert_eq!(g.right(), 78. + 12.);
         assert_eq!(g.top(), 52. - 11.);
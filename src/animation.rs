@@ -0,0 +1,397 @@
+//! Animations of the properties, similar to the QML NumberAnimation, ColorAnimation and
+//! Behavior.
+//!
+//! The animations are driven by an `AnimationDriver`, which reads the time from a `Clock`.
+//! Whoever owns the driver calls `tick` at each frame while animations are running.
+//! A `ManualClock` lets the tests step the time without an event loop.
+
+use crate::properties::{batch, Property, SetHookFn, WeakProperty};
+use std::cell::{Cell, RefCell};
+use std::pin::Pin;
+use std::rc::{Rc, Weak};
+use std::time::{Duration, Instant};
+
+/// The source of time of an AnimationDriver
+pub trait Clock {
+    /// The time elapsed since an arbitrary origin, which must not go backward
+    fn now(&self) -> Duration;
+}
+
+/// A Clock using the system monotonic clock
+pub struct SystemClock(Instant);
+impl Default for SystemClock {
+    fn default() -> Self {
+        SystemClock(Instant::now())
+    }
+}
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.0.elapsed()
+    }
+}
+
+/// A Clock which only moves when told to
+#[derive(Default)]
+pub struct ManualClock(Cell<Duration>);
+impl ManualClock {
+    pub fn advance(&self, d: Duration) {
+        self.0.set(self.0.get() + d);
+    }
+}
+impl Clock for ManualClock {
+    fn now(&self) -> Duration {
+        self.0.get()
+    }
+}
+
+/// A type whose values can be interpolated by an animation
+pub trait Animatable: Clone {
+    /// The value at `t` (between 0 and 1) between self and `to`
+    fn interpolate(&self, to: &Self, t: f64) -> Self;
+}
+impl Animatable for f64 {
+    fn interpolate(&self, to: &Self, t: f64) -> Self {
+        self + (to - self) * t
+    }
+}
+impl Animatable for f32 {
+    fn interpolate(&self, to: &Self, t: f64) -> Self {
+        self + (to - self) * t as f32
+    }
+}
+#[cfg(feature = "qt")]
+impl Animatable for qmetaobject::QColor {
+    fn interpolate(&self, to: &Self, t: f64) -> Self {
+        let (r1, g1, b1, a1) = self.get_rgba();
+        let (r2, g2, b2, a2) = to.get_rgba();
+        qmetaobject::QColor::from_rgba_f(
+            r1.interpolate(&r2, t),
+            g1.interpolate(&g2, t),
+            b1.interpolate(&b2, t),
+            a1.interpolate(&a2, t),
+        )
+    }
+}
+
+/// The easing curves, mapping the progress of the animation to the progress of the value
+#[derive(Clone, Copy, Default)]
+pub enum Easing {
+    #[default]
+    Linear,
+    InQuad,
+    OutQuad,
+    InOutQuad,
+    InCubic,
+    OutCubic,
+    InOutCubic,
+    Custom(fn(f64) -> f64),
+}
+impl Easing {
+    pub fn apply(self, t: f64) -> f64 {
+        match self {
+            Easing::Linear => t,
+            Easing::InQuad => t * t,
+            Easing::OutQuad => t * (2. - t),
+            Easing::InOutQuad => {
+                if t < 0.5 {
+                    2. * t * t
+                } else {
+                    -1. + (4. - 2. * t) * t
+                }
+            }
+            Easing::InCubic => t * t * t,
+            Easing::OutCubic => {
+                let t = t - 1.;
+                t * t * t + 1.
+            }
+            Easing::InOutCubic => {
+                if t < 0.5 {
+                    4. * t * t * t
+                } else {
+                    let t = 2. * t - 2.;
+                    t * t * t / 2. + 1.
+                }
+            }
+            Easing::Custom(f) => f(t),
+        }
+    }
+}
+
+pub(crate) trait Running {
+    /// Update the value for the time `now`. Returns false once the animation is finished.
+    fn advance(&self, now: Duration) -> bool;
+}
+
+/// Runs the animations. Call `tick` at each frame while `is_running` is true.
+pub struct AnimationDriver<'a> {
+    clock: Rc<dyn Clock>,
+    running: RefCell<Vec<Weak<dyn Running + 'a>>>,
+    wakeup: RefCell<Option<Box<dyn Fn() + 'a>>>,
+}
+
+impl<'a> AnimationDriver<'a> {
+    pub fn new(clock: Rc<dyn Clock>) -> Rc<Self> {
+        Rc::new(AnimationDriver {
+            clock,
+            running: Default::default(),
+            wakeup: Default::default(),
+        })
+    }
+
+    pub fn now(&self) -> Duration {
+        self.clock.now()
+    }
+
+    /// The function is called when an animation starts while none was running, so that
+    /// the owner of the driver can start calling `tick`.
+    pub fn set_wakeup<F: Fn() + 'a>(&self, f: F) {
+        *self.wakeup.borrow_mut() = Some(Box::new(f));
+    }
+
+    pub fn is_running(&self) -> bool {
+        !self.running.borrow().is_empty()
+    }
+
+    /// Update the running animations to the current time of the clock.
+    /// Returns true if some animations are still running.
+    pub fn tick(&self) -> bool {
+        let now = self.now();
+        // Take the list, so that the animations can be started or stopped while advancing
+        let running = std::mem::take(&mut *self.running.borrow_mut());
+        let still_running: Vec<_> = batch(|| {
+            running
+                .into_iter()
+                .filter(|a| a.upgrade().is_some_and(|a| a.advance(now)))
+                .collect()
+        });
+        let mut r = self.running.borrow_mut();
+        for a in still_running {
+            if !r.iter().any(|x| x.ptr_eq(&a)) {
+                r.push(a);
+            }
+        }
+        !r.is_empty()
+    }
+
+    pub(crate) fn start(&self, animation: Weak<dyn Running + 'a>) {
+        let was_idle = {
+            let mut r = self.running.borrow_mut();
+            let was_idle = r.is_empty();
+            if !r.iter().any(|x| x.ptr_eq(&animation)) {
+                r.push(animation);
+            }
+            was_idle
+        };
+        if was_idle {
+            if let Some(wakeup) = &*self.wakeup.borrow() {
+                wakeup();
+            }
+        }
+    }
+
+    fn stop(&self, animation: &Weak<dyn Running + 'a>) {
+        self.running.borrow_mut().retain(|x| !x.ptr_eq(animation));
+    }
+}
+
+struct AnimationState<'a, T> {
+    driver: Rc<AnimationDriver<'a>>,
+    target: WeakProperty<'a, T>,
+    duration: Cell<Duration>,
+    easing: Cell<Easing>,
+    /// from, to, and the start time
+    run: RefCell<Option<(T, T, Duration)>>,
+}
+
+impl<'a, T: Animatable + Default> Running for AnimationState<'a, T> {
+    fn advance(&self, now: Duration) -> bool {
+        let (value, finished) = match &*self.run.borrow() {
+            None => return false,
+            Some((from, to, start)) => {
+                let elapsed = now.checked_sub(*start).unwrap_or_default();
+                let duration = self.duration.get();
+                if elapsed >= duration {
+                    (to.clone(), true)
+                } else {
+                    let t = elapsed.as_secs_f64() / duration.as_secs_f64();
+                    (from.interpolate(to, self.easing.get().apply(t)), false)
+                }
+            }
+        };
+        if finished {
+            *self.run.borrow_mut() = None;
+        }
+        self.target.set(value);
+        !finished
+    }
+}
+
+/// Animates a property from a value to another. The animation stops if it is dropped.
+pub struct PropertyAnimation<'a, T> {
+    state: Rc<AnimationState<'a, T>>,
+}
+
+/// Same as the QML NumberAnimation
+pub type NumberAnimation<'a> = PropertyAnimation<'a, f64>;
+/// Same as the QML ColorAnimation
+#[cfg(feature = "qt")]
+pub type ColorAnimation<'a> = PropertyAnimation<'a, qmetaobject::QColor>;
+
+impl<'a, T: Animatable + Default + 'a> PropertyAnimation<'a, T> {
+    /// A linear animation of the target, lasting 250ms
+    pub fn new(driver: &Rc<AnimationDriver<'a>>, target: Pin<&Property<'a, T>>) -> Self {
+        PropertyAnimation {
+            state: Rc::new(AnimationState {
+                driver: driver.clone(),
+                target: target.as_weak(),
+                duration: Cell::new(Duration::from_millis(250)),
+                easing: Cell::default(),
+                run: RefCell::default(),
+            }),
+        }
+    }
+
+    pub fn set_duration(&self, duration: Duration) {
+        self.state.duration.set(duration);
+    }
+
+    pub fn set_easing(&self, easing: Easing) {
+        self.state.easing.set(easing);
+    }
+
+    /// Set the target to `from`, and animate it to `to`.
+    /// The binding of the target, if any, is removed.
+    pub fn start(&self, from: T, to: T) {
+        self.state.target.set(from.clone());
+        *self.state.run.borrow_mut() = Some((from, to, self.state.driver.now()));
+        self.state.driver.start(self.as_running());
+    }
+
+    /// Animate the target from its current value to `to`
+    pub fn animate_to(&self, to: T) {
+        if let Some(from) = self.state.target.get() {
+            self.start(from, to);
+        }
+    }
+
+    /// Stop the animation, leaving the target to its current value
+    pub fn stop(&self) {
+        *self.state.run.borrow_mut() = None;
+        self.state.driver.stop(&self.as_running());
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.state.run.borrow().is_some()
+    }
+
+    fn as_running(&self) -> Weak<dyn Running + 'a> {
+        let state: Rc<dyn Running + 'a> = self.state.clone();
+        Rc::downgrade(&state)
+    }
+}
+
+/// Animates the changes done with `Property::set`, like the QML Behavior.
+/// The property is changed without animation again when the Behavior is dropped.
+///
+/// Unlike in QML, only `set` is animated: when the property has a binding, its re-evaluations
+/// change the value at once. To animate a computed value, set the animated property from an
+/// `on_notify` callback of the property computing it.
+#[must_use]
+pub struct Behavior<'a, T> {
+    animation: Rc<PropertyAnimation<'a, T>>,
+    target: WeakProperty<'a, T>,
+    installed: SetHookFn<'a, T>,
+}
+
+impl<'a, T: Animatable + Default + 'a> Behavior<'a, T> {
+    /// Install the behavior on the property, replacing the previous one.
+    pub fn new(driver: &Rc<AnimationDriver<'a>>, target: Pin<&Property<'a, T>>) -> Self {
+        let animation = Rc::new(PropertyAnimation::new(driver, target));
+        let a = animation.clone();
+        let installed: SetHookFn<'a, T> = Rc::new(move |t| a.animate_to(t));
+        *target.set_hook().borrow_mut() = Some(installed.clone());
+        Behavior {
+            animation,
+            target: target.as_weak(),
+            installed,
+        }
+    }
+
+    /// The animation used, to change its duration and easing curve
+    pub fn animation(&self) -> &PropertyAnimation<'a, T> {
+        &self.animation
+    }
+}
+
+impl<'a, T> Drop for Behavior<'a, T> {
+    fn drop(&mut self) {
+        let installed = &self.installed;
+        self.target.with_property(|p| {
+            let mut hook = p.set_hook().borrow_mut();
+            // Unless it was replaced by another Behavior
+            if hook.as_ref().is_some_and(|h| Rc::ptr_eq(h, installed)) {
+                *hook = None;
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::properties::RcProperty;
+
+    #[test]
+    fn test_animation() {
+        let clock = Rc::new(ManualClock::default());
+        let driver = AnimationDriver::new(clock.clone());
+        let x = RcProperty::from(0.);
+        let double = RcProperty::from_binding(|| x.get() * 2.);
+        let anim = NumberAnimation::new(&driver, x.property());
+        anim.set_duration(Duration::from_millis(100));
+        anim.start(10., 20.);
+        assert_eq!(x.get(), 10.);
+        assert!(driver.is_running());
+        clock.advance(Duration::from_millis(25));
+        assert!(driver.tick());
+        assert_eq!(x.get(), 12.5);
+        assert_eq!(double.get(), 25.);
+        anim.set_easing(Easing::InQuad);
+        clock.advance(Duration::from_millis(25));
+        driver.tick();
+        assert_eq!(x.get(), 12.5);
+        clock.advance(Duration::from_millis(500));
+        assert!(!driver.tick());
+        assert_eq!(x.get(), 20.);
+        assert!(!anim.is_running());
+    }
+
+    #[test]
+    fn test_behavior() {
+        let clock = Rc::new(ManualClock::default());
+        let driver = AnimationDriver::new(clock.clone());
+        let woken = Rc::new(Cell::new(0));
+        let woken2 = woken.clone();
+        driver.set_wakeup(move || woken2.set(woken2.get() + 1));
+        let x = RcProperty::from(0.);
+        let behavior = Behavior::new(&driver, x.property());
+        behavior.animation().set_duration(Duration::from_secs(1));
+        x.set(100.);
+        assert_eq!(woken.get(), 1);
+        assert_eq!(x.get(), 0.);
+        clock.advance(Duration::from_millis(500));
+        driver.tick();
+        assert_eq!(x.get(), 50.);
+        // Changing the value again starts from the current one
+        x.set(0.);
+        clock.advance(Duration::from_millis(500));
+        driver.tick();
+        assert_eq!(x.get(), 25.);
+        assert_eq!(woken.get(), 1);
+
+        core::mem::drop(behavior);
+        x.set(3.);
+        assert_eq!(x.get(), 3.);
+        assert!(!driver.tick());
+    }
+}
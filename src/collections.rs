@@ -0,0 +1,500 @@
+//! Properties holding a collection, which report what changed instead of "the whole collection
+//! changed".
+//!
+//! A binding which reads the length of a `ListProperty`, or a single index, is only re-evaluated
+//! when that length or the item at that index changes. The callbacks connected with `on_change`
+//! receive the structured changes (insert, remove, move, update), for example to update only the
+//! affected items of a view. They are called once the dependent properties were notified.
+//!
+//! Like `Property`, the collections are stored inline and must be pinned.
+
+use crate::properties::{batch, Connection, Property, Signal, WeakProperty};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::pin::Pin;
+use std::rc::Rc;
+
+/// A property notified when the item at an index, or with a key, changes.
+/// Created when it is first accessed, and boxed so that it stays pinned when the map changes.
+type Tracker<'a> = Pin<Box<Property<'a, ()>>>;
+
+fn tracker<'a>() -> Tracker<'a> {
+    Box::pin(Property::default())
+}
+
+/// A change of a ListProperty, with the indices after the change
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListChange {
+    Inserted(usize),
+    Removed(usize),
+    /// From the first index to the second one
+    Moved(usize, usize),
+    Updated(usize),
+    /// The whole list was replaced
+    Reset,
+}
+
+/// A list of values, see the module documentation.
+pub struct ListProperty<'a, T> {
+    /// Notified on every change, for the bindings which read the whole list.
+    /// First, so that it is dropped first: its WeakProperty tells if the list is alive.
+    changed: Property<'a, ()>,
+    values: RefCell<Vec<T>>,
+    len: Property<'a, usize>,
+    /// Only for the indices in range, they are dropped when the list shrinks, see `notify`
+    indices: RefCell<BTreeMap<usize, Tracker<'a>>>,
+    /// Shared by the indices out of range, notified when items are inserted
+    past_end: Property<'a, ()>,
+    changes: Signal<'a, ListChange>,
+}
+
+impl<'a, T> Default for ListProperty<'a, T> {
+    fn default() -> Self {
+        ListProperty::from(Vec::new())
+    }
+}
+
+impl<'a, T> From<Vec<T>> for ListProperty<'a, T> {
+    fn from(values: Vec<T>) -> Self {
+        ListProperty {
+            len: Property::from(values.len()),
+            values: RefCell::new(values),
+            changed: Default::default(),
+            indices: Default::default(),
+            past_end: Default::default(),
+            changes: Default::default(),
+        }
+    }
+}
+
+impl<'a, T> ListProperty<'a, T> {
+    fn len_property(self: Pin<&Self>) -> Pin<&Property<'a, usize>> {
+        unsafe { self.map_unchecked(|s| &s.len) }
+    }
+
+    fn changed_property(self: Pin<&Self>) -> Pin<&Property<'a, ()>> {
+        unsafe { self.map_unchecked(|s| &s.changed) }
+    }
+
+    fn past_end_property(self: Pin<&Self>) -> Pin<&Property<'a, ()>> {
+        unsafe { self.map_unchecked(|s| &s.past_end) }
+    }
+
+    /// The length. Accessed from a binding, only a change of the length re-evaluates it.
+    pub fn len(self: Pin<&Self>) -> usize {
+        self.len_property().get()
+    }
+
+    pub fn is_empty(self: Pin<&Self>) -> bool {
+        self.len() == 0
+    }
+
+    /// Call `f` with the item at this index, or None if it is out of range.
+    /// Accessed from a binding, it is re-evaluated when the item at this index changes, including
+    /// when it is shifted by an insertion or a removal before it.
+    pub fn with_item<R>(self: Pin<&Self>, index: usize, f: impl FnOnce(Option<&T>) -> R) -> R {
+        if index < self.values.borrow().len() {
+            let mut indices = self.indices.borrow_mut();
+            indices.entry(index).or_insert_with(tracker).as_ref().get();
+        } else {
+            self.past_end_property().get();
+        }
+        f(self.values.borrow().get(index))
+    }
+
+    /// Call `f` with all the items. Accessed from a binding, any change re-evaluates it.
+    /// Changing the list from `f` panics.
+    pub fn with<R>(self: Pin<&Self>, f: impl FnOnce(&[T]) -> R) -> R {
+        self.changed_property().get();
+        f(&self.values.borrow())
+    }
+
+    pub fn push(self: Pin<&Self>, value: T) {
+        let index = self.values.borrow().len();
+        self.insert(index, value);
+    }
+
+    /// Panics if `index > len`
+    pub fn insert(self: Pin<&Self>, index: usize, value: T) {
+        self.values.borrow_mut().insert(index, value);
+        self.notify(index..usize::MAX, ListChange::Inserted(index));
+    }
+
+    /// Panics if `index` is out of range
+    pub fn remove(self: Pin<&Self>, index: usize) -> T {
+        let value = self.values.borrow_mut().remove(index);
+        self.notify(index..usize::MAX, ListChange::Removed(index));
+        value
+    }
+
+    /// Move the item at `from` to the index `to`, shifting the items in between.
+    /// Panics if one of them is out of range.
+    pub fn move_item(self: Pin<&Self>, from: usize, to: usize) {
+        {
+            let mut values = self.values.borrow_mut();
+            if from < to {
+                values[from..=to].rotate_left(1);
+            } else {
+                values[to..=from].rotate_right(1);
+            }
+        }
+        self.notify(from.min(to)..from.max(to) + 1, ListChange::Moved(from, to));
+    }
+
+    /// Replace the item at this index. Panics if it is out of range.
+    pub fn set(self: Pin<&Self>, index: usize, value: T) {
+        let previous = std::mem::replace(&mut self.values.borrow_mut()[index], value);
+        // Dropped after the borrow, in case it owns something accessing the list
+        drop(previous);
+        self.notify(index..index + 1, ListChange::Updated(index));
+    }
+
+    /// Replace all the items
+    pub fn replace(self: Pin<&Self>, values: Vec<T>) {
+        let previous = std::mem::replace(&mut *self.values.borrow_mut(), values);
+        drop(previous);
+        self.notify(0..usize::MAX, ListChange::Reset);
+    }
+
+    pub fn clear(self: Pin<&Self>) {
+        self.replace(Vec::new())
+    }
+
+    /// The callback is called after each change of the list.
+    pub fn on_change<F: Fn(ListChange) + 'a>(&self, callback: F) -> Connection<'a> {
+        self.changes.connect(callback)
+    }
+
+    /// Register the Rc which contains this list, see `Property::set_owner`
+    pub fn set_owner<I: 'a>(self: Pin<&Self>, owner: &Pin<Rc<I>>) {
+        self.changed_property().set_owner(owner);
+        self.len_property().set_owner(owner);
+    }
+
+    pub fn as_weak(self: Pin<&Self>) -> WeakListProperty<'a, T> {
+        WeakListProperty {
+            alive: self.changed_property().as_weak(),
+            list: &*self,
+        }
+    }
+
+    /// Notify the bindings depending on the indices in the range, or on the length if it changed.
+    fn notify(self: Pin<&Self>, range: Range<usize>, change: ListChange) {
+        batch(|| {
+            self.len_property()
+                .set_if_changed(self.values.borrow().len());
+            self.changed_property().set(());
+            if let ListChange::Inserted(_) | ListChange::Reset = change {
+                self.past_end_property().set(());
+            }
+            let mut indices = self.indices.borrow_mut();
+            for i in indices.range(range).map(|x| x.1) {
+                i.as_ref().set(());
+            }
+            // The bindings depending on the trackers past the end are now dirty, and they
+            // depend on `past_end` if they read these indices again. So these can be dropped.
+            let len = self.values.borrow().len();
+            drop(indices.split_off(&len));
+        });
+        self.changes.emit(change);
+    }
+}
+
+/// A weak reference to a ListProperty, see WeakProperty
+pub struct WeakListProperty<'a, T> {
+    alive: WeakProperty<'a, ()>,
+    list: *const ListProperty<'a, T>,
+}
+impl<'a, T> Clone for WeakListProperty<'a, T> {
+    fn clone(&self) -> Self {
+        WeakListProperty {
+            alive: self.alive.clone(),
+            list: self.list,
+        }
+    }
+}
+impl<'a, T> WeakListProperty<'a, T> {
+    /// Call the function with the list, if it still exists
+    pub fn with_list<R>(&self, f: impl FnOnce(Pin<&ListProperty<'a, T>>) -> R) -> Option<R> {
+        // The list cannot be dropped while its first field is in use
+        self.alive
+            .with_property(|_| f(unsafe { Pin::new_unchecked(&*self.list) }))
+    }
+}
+
+impl<'a, T: Clone> ListProperty<'a, T> {
+    /// The item at this index. See `with_item`.
+    pub fn get(self: Pin<&Self>, index: usize) -> Option<T> {
+        self.with_item(index, |v| v.cloned())
+    }
+
+    pub fn to_vec(self: Pin<&Self>) -> Vec<T> {
+        self.with(|v| v.to_vec())
+    }
+}
+
+/// A change of a MapProperty
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MapChange<K> {
+    Inserted(K),
+    Removed(K),
+    Updated(K),
+    /// The whole map was replaced
+    Reset,
+}
+
+/// A map of values, sorted by key. Same as ListProperty, a binding which reads the value of a
+/// key only depends on that key.
+pub struct MapProperty<'a, K, V> {
+    values: RefCell<BTreeMap<K, V>>,
+    len: Property<'a, usize>,
+    changed: Property<'a, ()>,
+    /// Dropped when their key is removed, see `notify`
+    keys: RefCell<BTreeMap<K, Tracker<'a>>>,
+    changes: Signal<'a, MapChange<K>>,
+}
+
+impl<'a, K: Ord, V> Default for MapProperty<'a, K, V> {
+    fn default() -> Self {
+        MapProperty::from(BTreeMap::new())
+    }
+}
+
+impl<'a, K: Ord, V> From<BTreeMap<K, V>> for MapProperty<'a, K, V> {
+    fn from(values: BTreeMap<K, V>) -> Self {
+        MapProperty {
+            len: Property::from(values.len()),
+            values: RefCell::new(values),
+            changed: Default::default(),
+            keys: Default::default(),
+            changes: Default::default(),
+        }
+    }
+}
+
+impl<'a, K: Ord + Clone + 'a, V> MapProperty<'a, K, V> {
+    fn len_property(self: Pin<&Self>) -> Pin<&Property<'a, usize>> {
+        unsafe { self.map_unchecked(|s| &s.len) }
+    }
+
+    fn changed_property(self: Pin<&Self>) -> Pin<&Property<'a, ()>> {
+        unsafe { self.map_unchecked(|s| &s.changed) }
+    }
+
+    /// The number of entries. Accessed from a binding, only a change of the length
+    /// re-evaluates it.
+    pub fn len(self: Pin<&Self>) -> usize {
+        self.len_property().get()
+    }
+
+    pub fn is_empty(self: Pin<&Self>) -> bool {
+        self.len() == 0
+    }
+
+    /// Call `f` with the value of this key, or None if there is none.
+    /// Accessed from a binding, it is re-evaluated when the value of this key changes.
+    pub fn with_value<R>(self: Pin<&Self>, key: &K, f: impl FnOnce(Option<&V>) -> R) -> R {
+        {
+            let mut keys = self.keys.borrow_mut();
+            if !keys.contains_key(key) {
+                keys.insert(key.clone(), tracker());
+            }
+            keys[key].as_ref().get();
+        }
+        f(self.values.borrow().get(key))
+    }
+
+    pub fn contains_key(self: Pin<&Self>, key: &K) -> bool {
+        self.with_value(key, |v| v.is_some())
+    }
+
+    /// Call `f` with the whole map. Accessed from a binding, any change re-evaluates it.
+    /// Changing the map from `f` panics.
+    pub fn with<R>(self: Pin<&Self>, f: impl FnOnce(&BTreeMap<K, V>) -> R) -> R {
+        self.changed_property().get();
+        f(&self.values.borrow())
+    }
+
+    /// Returns the previous value
+    pub fn insert(self: Pin<&Self>, key: K, value: V) -> Option<V> {
+        let previous = self.values.borrow_mut().insert(key.clone(), value);
+        let change = match previous {
+            Some(_) => MapChange::Updated(key),
+            None => MapChange::Inserted(key),
+        };
+        self.notify(change);
+        previous
+    }
+
+    pub fn remove(self: Pin<&Self>, key: &K) -> Option<V> {
+        let previous = self.values.borrow_mut().remove(key);
+        if previous.is_some() {
+            self.notify(MapChange::Removed(key.clone()));
+        }
+        previous
+    }
+
+    /// Replace all the entries
+    pub fn replace(self: Pin<&Self>, values: BTreeMap<K, V>) {
+        let previous = std::mem::replace(&mut *self.values.borrow_mut(), values);
+        drop(previous);
+        self.notify(MapChange::Reset);
+    }
+
+    pub fn clear(self: Pin<&Self>) {
+        self.replace(BTreeMap::new())
+    }
+
+    /// The callback is called after each change of the map.
+    pub fn on_change<F: Fn(MapChange<K>) + 'a>(&self, callback: F) -> Connection<'a> {
+        self.changes.connect(callback)
+    }
+
+    fn notify(self: Pin<&Self>, change: MapChange<K>) {
+        batch(|| {
+            self.len_property()
+                .set_if_changed(self.values.borrow().len());
+            self.changed_property().set(());
+            let mut keys = self.keys.borrow_mut();
+            match &change {
+                MapChange::Inserted(k) | MapChange::Removed(k) | MapChange::Updated(k) => {
+                    if let Some(t) = keys.get(k) {
+                        t.as_ref().set(());
+                    }
+                }
+                MapChange::Reset => keys.values().for_each(|t| t.as_ref().set(())),
+            }
+            // The bindings depending on the trackers of the removed keys are now dirty: if they
+            // read the key again, they create a new tracker. So these ones can be dropped.
+            match &change {
+                MapChange::Removed(k) => drop(keys.remove(k)),
+                MapChange::Reset => {
+                    let values = self.values.borrow();
+                    keys.retain(|k, _| values.contains_key(k));
+                }
+                _ => {}
+            }
+        });
+        self.changes.emit(change);
+    }
+}
+
+impl<'a, K: Ord + Clone + 'a, V: Clone> MapProperty<'a, K, V> {
+    /// The value of this key. See `with_value`.
+    pub fn get(self: Pin<&Self>, key: &K) -> Option<V> {
+        self.with_value(key, |v| v.cloned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::properties::RcProperty;
+    use std::cell::Cell;
+    use std::pin::pin;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_list() {
+        let list = pin!(ListProperty::from(vec![1, 2, 3]));
+        let list = list.into_ref();
+        let evaluations = Rc::new(Cell::new(0));
+        let e = evaluations.clone();
+        let first = RcProperty::from_binding(move || {
+            e.set(e.get() + 1);
+            list.get(0)
+        });
+        let len = RcProperty::from_binding(|| list.len());
+        let sum = RcProperty::from_binding(|| list.with(|v| v.iter().sum::<i32>()));
+        let changes = Rc::new(RefCell::new(Vec::new()));
+        let c = changes.clone();
+        list.on_change(move |change| c.borrow_mut().push(change));
+
+        assert_eq!(first.get(), Some(1));
+        assert_eq!((len.get(), sum.get()), (3, 6));
+        list.push(4);
+        list.set(2, 10);
+        assert_eq!(first.get(), Some(1));
+        assert_eq!(evaluations.get(), 1);
+        assert_eq!((len.get(), sum.get()), (4, 17));
+        list.insert(0, 5);
+        assert_eq!(first.get(), Some(5));
+        list.move_item(0, 3);
+        assert_eq!(list.to_vec(), vec![1, 2, 10, 5, 4]);
+        assert_eq!(list.remove(1), 2);
+        assert_eq!(first.get(), Some(1));
+        assert_eq!(evaluations.get(), 3);
+        list.clear();
+        assert_eq!((first.get(), len.get(), sum.get()), (None, 0, 0));
+        assert_eq!(
+            *changes.borrow(),
+            vec![
+                ListChange::Inserted(3),
+                ListChange::Updated(2),
+                ListChange::Inserted(0),
+                ListChange::Moved(0, 3),
+                ListChange::Removed(1),
+                ListChange::Reset,
+            ]
+        );
+        // The indices out of range share a tracker
+        assert_eq!(list.get(usize::MAX), None);
+        assert!(list.indices.borrow().is_empty());
+        list.push(7);
+        assert_eq!(first.get(), Some(7));
+        list.push(8);
+        list.remove(0);
+        assert_eq!(list.indices.borrow().len(), 1);
+    }
+
+    #[test]
+    fn test_map() {
+        let map = pin!(MapProperty::default());
+        let map = map.into_ref();
+        let evaluations = Rc::new(Cell::new(0));
+        let e = evaluations.clone();
+        let foo = RcProperty::from_binding(move || {
+            e.set(e.get() + 1);
+            map.get(&"foo")
+        });
+        let len = RcProperty::from_binding(|| map.len());
+        let changes = Rc::new(RefCell::new(Vec::new()));
+        let c = changes.clone();
+        map.on_change(move |change| c.borrow_mut().push(change));
+
+        assert_eq!(foo.get(), None);
+        map.insert("bar", 1);
+        assert_eq!((foo.get(), len.get()), (None, 1));
+        assert_eq!(evaluations.get(), 1);
+        map.insert("foo", 2);
+        assert_eq!((foo.get(), len.get()), (Some(2), 2));
+        map.insert("foo", 3);
+        assert_eq!(foo.get(), Some(3));
+        assert_eq!(map.remove(&"foo"), Some(3));
+        assert_eq!(map.remove(&"foo"), None);
+        assert_eq!((foo.get(), len.get()), (None, 1));
+        // The binding still waits for the key
+        map.insert("foo", 4);
+        assert_eq!(foo.get(), Some(4));
+        map.remove(&"foo");
+        drop(foo);
+        assert!(!map.keys.borrow().contains_key(&"foo"));
+        map.insert("foo", 5);
+        map.replace(BTreeMap::new());
+        assert!(map.keys.borrow().is_empty());
+        assert_eq!(
+            *changes.borrow(),
+            vec![
+                MapChange::Inserted("bar"),
+                MapChange::Inserted("foo"),
+                MapChange::Updated("foo"),
+                MapChange::Removed("foo"),
+                MapChange::Inserted("foo"),
+                MapChange::Removed("foo"),
+                MapChange::Inserted("foo"),
+                MapChange::Reset,
+            ]
+        );
+    }
+}
@@ -0,0 +1,422 @@
+//! Combinators deriving a property from others, without the `as_weak` and `set_binding`
+//! boilerplate:
+//!
+//! ```ignore
+//! let area = width.combine(height, |w, h| w * h);
+//! let label = area.map(|a| format!("{} px", a)).distinct();
+//! ```
+//!
+//! The result is a `Derived`, which can only be read, as a `ReadOnlyProperty`. It only keeps
+//! weak references to its sources: once a source is dropped, it keeps its last value.
+//! `debounce` and `throttle` are driven by an `AnimationDriver`, which provides the time.
+
+use crate::animation::{AnimationDriver, Running};
+use crate::collections::ListProperty;
+use crate::properties::{
+    Connection, Keep, Property, RcProperty, ReadOnlyProperty, ScopedConnection, WeakProperty,
+};
+use crate::properties_impl;
+use std::cell::{Cell, RefCell};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::time::Duration;
+
+/// A binding which keeps the current value when the function returns None, because a source was
+/// dropped or a filter rejected the value. Unlike a PropertyBindingFn, this is not an error.
+struct Follow<F>(F);
+impl<T: Default, F: Fn() -> Option<T>> properties_impl::Binding<T> for Follow<F> {
+    fn call(self: Pin<&Self>) -> T {
+        self.try_call().unwrap_or_default()
+    }
+    fn try_call(self: Pin<&Self>) -> Option<T> {
+        (self.0)()
+    }
+}
+
+/// A property computed by a combinator. It can be read, and combined further, but not set.
+/// The Derived computed from it keep it alive, so the combinators can be chained.
+pub struct Derived<'a, T>(Rc<DerivedState<'a, T>>);
+
+struct DerivedState<'a, T> {
+    /// Disconnects the callback on the source, if the combinator needs one
+    connection: Option<ScopedConnection<'a>>,
+    /// The Derived it was computed from
+    source: Option<Rc<dyn Keep + 'a>>,
+    property: RcProperty<'a, T>,
+}
+
+impl<'a, T: Default + 'a> Derived<'a, T> {
+    fn from_binding<B: properties_impl::Binding<T> + 'a>(binding: B) -> Self {
+        let property = RcProperty::default();
+        property.property().d().set_binding_owned(binding);
+        Derived::new(property)
+    }
+}
+
+impl<'a, T: 'a> Derived<'a, T> {
+    fn new(property: RcProperty<'a, T>) -> Self {
+        Derived(Rc::new(DerivedState {
+            connection: None,
+            source: None,
+            property,
+        }))
+    }
+
+    fn from_value(value: T) -> Self {
+        Derived::new(RcProperty::from(value))
+    }
+
+    fn state_mut(&mut self) -> &mut DerivedState<'a, T> {
+        Rc::get_mut(&mut self.0).expect("only called on a new Derived")
+    }
+
+    fn with_connection(mut self, c: Connection<'a>) -> Self {
+        self.state_mut().connection = Some(c.scoped());
+        self
+    }
+
+    fn keeping<U: 'a>(mut self, source: &Derived<'a, U>) -> Self {
+        self.state_mut().source = Some(source.0.clone());
+        self
+    }
+
+    /// The derived property. Not public, since setting it would break the combinator
+    fn property(&self) -> Pin<&Property<'a, T>> {
+        self.0.property.property()
+    }
+
+    // Same as the ones of ReadOnlyProperty, but the result keeps this one alive
+    pub fn map<U: Default + 'a, F: Fn(&T) -> U + 'a>(&self, f: F) -> Derived<'a, U> {
+        self.property().map(f).keeping(self)
+    }
+    pub fn combine<U: 'a, R: Default + 'a, F: Fn(&T, &U) -> R + 'a>(
+        &self,
+        other: &ReadOnlyProperty<'a, U>,
+        f: F,
+    ) -> Derived<'a, R> {
+        self.property().combine(other, f).keeping(self)
+    }
+    pub fn zip<U: Clone + Default + 'a>(
+        &self,
+        other: &ReadOnlyProperty<'a, U>,
+    ) -> Derived<'a, (T, U)>
+    where
+        T: Clone + Default,
+    {
+        self.property().zip(other).keeping(self)
+    }
+    pub fn filter<F: Fn(&T) -> bool + 'a>(&self, predicate: F) -> Derived<'a, T>
+    where
+        T: Clone + Default,
+    {
+        self.property().filter(predicate).keeping(self)
+    }
+    pub fn distinct(&self) -> Derived<'a, T>
+    where
+        T: Clone + Default + PartialEq,
+    {
+        self.property().distinct().keeping(self)
+    }
+    pub fn debounce(&self, driver: &Rc<AnimationDriver<'a>>, delay: Duration) -> Derived<'a, T>
+    where
+        T: Clone + Default,
+    {
+        self.property().debounce(driver, delay).keeping(self)
+    }
+    pub fn throttle(&self, driver: &Rc<AnimationDriver<'a>>, period: Duration) -> Derived<'a, T>
+    where
+        T: Clone + Default,
+    {
+        self.property().throttle(driver, period).keeping(self)
+    }
+}
+
+impl<'a, T> std::ops::Deref for Derived<'a, T> {
+    type Target = ReadOnlyProperty<'a, T>;
+    fn deref(&self) -> &ReadOnlyProperty<'a, T> {
+        self.0.property.as_read_only()
+    }
+}
+
+impl<'a, T: 'a> ReadOnlyProperty<'a, T> {
+    pub fn map<U: Default + 'a, F: Fn(&T) -> U + 'a>(&self, f: F) -> Derived<'a, U> {
+        self.property().map(f)
+    }
+    pub fn combine<U: 'a, R: Default + 'a, F: Fn(&T, &U) -> R + 'a>(
+        &self,
+        other: &ReadOnlyProperty<'a, U>,
+        f: F,
+    ) -> Derived<'a, R> {
+        self.property().combine(other, f)
+    }
+    pub fn zip<U: Clone + Default + 'a>(
+        &self,
+        other: &ReadOnlyProperty<'a, U>,
+    ) -> Derived<'a, (T, U)>
+    where
+        T: Clone + Default,
+    {
+        self.property().zip(other)
+    }
+    pub fn filter<F: Fn(&T) -> bool + 'a>(&self, predicate: F) -> Derived<'a, T>
+    where
+        T: Clone + Default,
+    {
+        self.property().filter(predicate)
+    }
+    pub fn distinct(&self) -> Derived<'a, T>
+    where
+        T: Clone + Default + PartialEq,
+    {
+        self.property().distinct()
+    }
+    pub fn debounce(&self, driver: &Rc<AnimationDriver<'a>>, delay: Duration) -> Derived<'a, T>
+    where
+        T: Clone + Default,
+    {
+        self.property().debounce(driver, delay)
+    }
+    pub fn throttle(&self, driver: &Rc<AnimationDriver<'a>>, period: Duration) -> Derived<'a, T>
+    where
+        T: Clone + Default,
+    {
+        self.property().throttle(driver, period)
+    }
+}
+
+impl<'a, T: 'a> Property<'a, T> {
+    /// A property with the value of `f` applied to the value of this one
+    pub fn map<U: Default + 'a, F: Fn(&T) -> U + 'a>(self: Pin<&Self>, f: F) -> Derived<'a, U> {
+        let source = self.as_weak();
+        Derived::from_binding(Follow(move || source.with_property(|s| s.with(&f))))
+    }
+
+    /// A property with the value of `f` applied to the values of this one and `other`.
+    /// Chain them to combine more properties.
+    pub fn combine<U: 'a, R: Default + 'a, F: Fn(&T, &U) -> R + 'a>(
+        self: Pin<&Self>,
+        other: &ReadOnlyProperty<'a, U>,
+        f: F,
+    ) -> Derived<'a, R> {
+        let (a, b) = (self.as_weak(), other.property().as_weak());
+        Derived::from_binding(Follow(move || {
+            a.with_property(|a| b.with_property(|b| a.with(|a| b.with(|b| f(a, b)))))
+                .flatten()
+        }))
+    }
+
+    /// A property with the values of this one and `other`
+    pub fn zip<U: Clone + Default + 'a>(
+        self: Pin<&Self>,
+        other: &ReadOnlyProperty<'a, U>,
+    ) -> Derived<'a, (T, U)>
+    where
+        T: Clone + Default,
+    {
+        self.combine(other, |a, b| (a.clone(), b.clone()))
+    }
+
+    /// A property which follows this one, but only takes the values accepted by the predicate.
+    /// Until one is, it has the default value.
+    /// Unlike the bindings, it is updated at each change, so no value is missed.
+    pub fn filter<F: Fn(&T) -> bool + 'a>(self: Pin<&Self>, predicate: F) -> Derived<'a, T>
+    where
+        T: Clone + Default,
+    {
+        let derived = Derived::from_value(self.with(|v| {
+            if predicate(v) {
+                v.clone()
+            } else {
+                T::default()
+            }
+        }));
+        let target = derived.property().as_weak();
+        let c = self.on_notify(move |v| {
+            if predicate(v) {
+                target.set(v.clone());
+            }
+        });
+        derived.with_connection(c)
+    }
+
+    /// A property which follows this one, but only notifies when the value actually changes.
+    pub fn distinct(self: Pin<&Self>) -> Derived<'a, T>
+    where
+        T: Clone + Default + PartialEq,
+    {
+        let source = self.as_weak();
+        Derived::from_binding(properties_impl::IfChanged(Follow(move || source.get())))
+    }
+
+    /// A property which takes the value of this one once it did not change for `delay`.
+    pub fn debounce(
+        self: Pin<&Self>,
+        driver: &Rc<AnimationDriver<'a>>,
+        delay: Duration,
+    ) -> Derived<'a, T>
+    where
+        T: Clone + Default,
+    {
+        let derived = Derived::from_value(self.get());
+        let state = Rc::new(Delayed {
+            target: derived.property().as_weak(),
+            period: delay,
+            last: Cell::new(None),
+            pending: RefCell::new(None),
+        });
+        let driver = driver.clone();
+        let running: Rc<dyn Running + 'a> = state.clone();
+        let running = Rc::downgrade(&running);
+        let c = self.on_notify(move |v| {
+            // The delay restarts at each change
+            state.last.set(Some(driver.now()));
+            *state.pending.borrow_mut() = Some(v.clone());
+            driver.start(running.clone());
+        });
+        derived.with_connection(c)
+    }
+
+    /// A property which follows this one, but changes at most once per `period`. The last value
+    /// of a period is applied at the end of it.
+    pub fn throttle(
+        self: Pin<&Self>,
+        driver: &Rc<AnimationDriver<'a>>,
+        period: Duration,
+    ) -> Derived<'a, T>
+    where
+        T: Clone + Default,
+    {
+        let derived = Derived::from_value(self.get());
+        let state = Rc::new(Delayed {
+            target: derived.property().as_weak(),
+            period,
+            last: Cell::new(None),
+            pending: RefCell::new(None),
+        });
+        let driver = driver.clone();
+        let running: Rc<dyn Running + 'a> = state.clone();
+        let running = Rc::downgrade(&running);
+        let c = self.on_notify(move |v| {
+            let now = driver.now();
+            if state.last.get().is_some_and(|last| now < last + period) {
+                *state.pending.borrow_mut() = Some(v.clone());
+                driver.start(running.clone());
+            } else {
+                state.last.set(Some(now));
+                state.target.set(v.clone());
+            }
+        });
+        derived.with_connection(c)
+    }
+}
+
+/// The state of debounce and throttle: the pending value is applied `period` after `last`
+struct Delayed<'a, T> {
+    target: WeakProperty<'a, T>,
+    period: Duration,
+    last: Cell<Option<Duration>>,
+    pending: RefCell<Option<T>>,
+}
+
+impl<'a, T> Running for Delayed<'a, T> {
+    fn advance(&self, now: Duration) -> bool {
+        if self.last.get().is_some_and(|last| now < last + self.period) {
+            return self.pending.borrow().is_some();
+        }
+        let pending = self.pending.borrow_mut().take();
+        if let Some(v) = pending {
+            self.last.set(Some(now));
+            self.target.set(v);
+        }
+        false
+    }
+}
+
+impl<'a, T: 'a> ListProperty<'a, T> {
+    /// A property with the result of folding the items, re-evaluated when the list changes
+    /// or when a property read by `f` changes.
+    pub fn fold<A, F>(self: Pin<&Self>, init: A, f: F) -> Derived<'a, A>
+    where
+        A: Clone + Default + 'a,
+        F: Fn(A, &T) -> A + 'a,
+    {
+        let list = self.as_weak();
+        Derived::from_binding(Follow(move || {
+            list.with_list(|l| l.with(|v| v.iter().fold(init.clone(), &f)))
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::animation::ManualClock;
+
+    #[test]
+    fn test_combinators() {
+        let width = RcProperty::from(2);
+        let height = RcProperty::from(3);
+        let area = width
+            .property()
+            .combine(height.as_read_only(), |w, h| w * h);
+        let label = area.map(|a| format!("{} px", a));
+        let even = area.filter(|a| a % 2 == 0);
+        let parity = area.map(|a| a % 2).distinct();
+        let notified = Rc::new(Cell::new(0));
+        let n = notified.clone();
+        parity.on_notify(move |_| n.set(n.get() + 1));
+        assert_eq!(label.get(), "6 px");
+        assert_eq!((even.get(), parity.get()), (6, 0));
+        width.set(4);
+        assert_eq!(label.get(), "12 px");
+        assert_eq!((even.get(), parity.get()), (12, 0));
+        assert_eq!(notified.get(), 0);
+        height.set(5);
+        assert_eq!(label.get(), "20 px");
+        width.set(5);
+        assert_eq!(label.get(), "25 px");
+        assert_eq!((even.get(), parity.get()), (20, 1));
+        assert_eq!(notified.get(), 1);
+        assert_eq!(width.property().zip(height.as_read_only()).get(), (5, 5));
+        // Keeps its last value once the source is dropped
+        std::mem::drop(width);
+        height.set(6);
+        assert_eq!(label.get(), "25 px");
+    }
+
+    #[test]
+    fn test_fold() {
+        let factor = RcProperty::from(1);
+        let owner = Rc::pin(ListProperty::from(vec![1, 2, 3]));
+        let list = owner.as_ref();
+        list.set_owner(&owner);
+        let sum = list.fold(0, |acc, v| acc + v * factor.get());
+        assert_eq!(sum.get(), 6);
+        list.push(4);
+        assert_eq!(sum.get(), 10);
+        factor.set(10);
+        assert_eq!(sum.get(), 100);
+    }
+
+    #[test]
+    fn test_debounce_throttle() {
+        let clock = Rc::new(ManualClock::default());
+        let driver = AnimationDriver::new(clock.clone());
+        let ms = Duration::from_millis;
+        let text = RcProperty::from(0);
+        let debounced = text.property().debounce(&driver, ms(100));
+        let throttled = text.property().throttle(&driver, ms(100));
+        text.set(1);
+        assert_eq!((debounced.get(), throttled.get()), (0, 1));
+        clock.advance(ms(60));
+        text.set(2);
+        driver.tick();
+        assert_eq!((debounced.get(), throttled.get()), (0, 1));
+        clock.advance(ms(60));
+        driver.tick();
+        assert_eq!((debounced.get(), throttled.get()), (0, 2));
+        clock.advance(ms(60));
+        assert!(!driver.tick());
+        assert_eq!((debounced.get(), throttled.get()), (2, 2));
+    }
+}
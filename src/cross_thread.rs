@@ -0,0 +1,294 @@
+//! Feeding properties from other threads.
+//!
+//! Properties are not thread safe: they must be read and written from the thread owning them.
+//! A `ThreadQueue` lives on that thread and hands out `Setter`s which can be sent to other
+//! threads. The values posted through a setter are applied when the owning thread calls
+//! `ThreadQueue::process`, so the bindings and callbacks behave as with `Property::set`.
+//! The slot of a property in the queue is reused once all its setters are dropped.
+
+use crate::properties::{batch, Property, WeakProperty};
+use std::any::Any;
+use std::cell::RefCell;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex, Weak};
+
+type Update = Box<dyn Any + Send>;
+type UpdateFn<T> = Box<dyn FnOnce(&T) -> T + Send>;
+/// Applies an update, returns false once the property is gone
+type Target<'a> = Rc<dyn Fn(Update) -> bool + 'a>;
+
+#[derive(Default)]
+struct Shared {
+    /// The updates, or None once the last setter of the target is dropped
+    pending: Mutex<Vec<(usize, Option<Update>)>>,
+    wakeup: Mutex<Option<Arc<dyn Fn() + Send + Sync>>>,
+}
+
+impl Shared {
+    fn post(&self, id: usize, update: Update) {
+        let was_empty = {
+            let mut pending = self.pending.lock().unwrap();
+            pending.push((id, Some(update)));
+            pending.len() == 1
+        };
+        if was_empty {
+            // Don't call it with the lock held
+            let wakeup = self.wakeup.lock().unwrap().clone();
+            if let Some(wakeup) = wakeup {
+                wakeup();
+            }
+        }
+    }
+}
+
+/// Receives the updates posted by the Setters, and applies them to the properties.
+/// It must stay on the thread owning the properties.
+#[derive(Default)]
+pub struct ThreadQueue<'a> {
+    shared: Arc<Shared>,
+    /// Indexed by the id of the setters. None once the property is gone.
+    targets: RefCell<Vec<Option<Target<'a>>>>,
+    /// The ids without setters, which can be reused
+    free: RefCell<Vec<usize>>,
+}
+
+impl<'a> ThreadQueue<'a> {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Returns a Setter which can be sent to another thread to change the property.
+    /// The updates are applied with `Property::set`, so they go through its Behavior, if any.
+    pub fn setter<T>(&self, property: Pin<&Property<'a, T>>) -> Setter<T>
+    where
+        T: Send + 'static,
+    {
+        let weak: WeakProperty<'a, T> = property.as_weak();
+        let target: Target<'a> = Rc::new(move |update: Update| {
+            let f = update
+                .downcast::<UpdateFn<T>>()
+                .expect("update of the wrong type");
+            weak.with_property(|p| p.set(p.with(f))).is_some()
+        });
+        let mut targets = self.targets.borrow_mut();
+        let id = match self.free.borrow_mut().pop() {
+            Some(id) => {
+                targets[id] = Some(target);
+                id
+            }
+            None => {
+                targets.push(Some(target));
+                targets.len() - 1
+            }
+        };
+        Setter {
+            handle: Arc::new(SetterHandle {
+                shared: Arc::downgrade(&self.shared),
+                id,
+            }),
+            _phantom: PhantomData,
+        }
+    }
+
+    /// The function is called from the posting thread when updates are pending and the
+    /// queue was empty. It should schedule a call to `process` on the owning thread,
+    /// for example by posting an event to its event loop.
+    pub fn set_wakeup<F: Fn() + Send + Sync + 'static>(&self, f: F) {
+        *self.shared.wakeup.lock().unwrap() = Some(Arc::new(f));
+    }
+
+    /// Apply all the pending updates, in the order they were posted.
+    /// The change callbacks are called once, after all the updates are applied.
+    /// Returns the number of updates.
+    pub fn process(&self) -> usize {
+        let pending = std::mem::take(&mut *self.shared.pending.lock().unwrap());
+        let mut count = 0;
+        batch(|| {
+            for (id, update) in pending {
+                let update = match update {
+                    Some(update) => update,
+                    None => {
+                        // All the setters are gone, so no update for this id can follow
+                        let target = self.targets.borrow_mut()[id].take();
+                        core::mem::drop(target);
+                        self.free.borrow_mut().push(id);
+                        continue;
+                    }
+                };
+                count += 1;
+                // Cloned so the callbacks can create new setters
+                let target = self.targets.borrow()[id].clone();
+                if let Some(target) = target {
+                    if !target(update) {
+                        // Keep the id reserved until the setters are dropped
+                        self.targets.borrow_mut()[id] = None;
+                    }
+                }
+            }
+        });
+        count
+    }
+}
+
+/// Changes a property from any thread. See `ThreadQueue::setter`.
+/// The updates are dropped if the queue or the property no longer exist.
+pub struct Setter<T> {
+    handle: Arc<SetterHandle>,
+    _phantom: PhantomData<fn(T)>,
+}
+
+impl<T> Clone for Setter<T> {
+    fn clone(&self) -> Self {
+        Setter {
+            handle: self.handle.clone(),
+            _phantom: PhantomData,
+        }
+    }
+}
+
+/// Shared by the clones of a Setter, releases the id when the last one is dropped
+struct SetterHandle {
+    shared: Weak<Shared>,
+    id: usize,
+}
+
+impl Drop for SetterHandle {
+    fn drop(&mut self) {
+        if let Some(shared) = self.shared.upgrade() {
+            // Applied with the next updates, no need to wake up
+            shared.pending.lock().unwrap().push((self.id, None));
+        }
+    }
+}
+
+impl<T: Send + 'static> Setter<T> {
+    pub fn set(&self, value: T) {
+        self.update(move |_| value)
+    }
+
+    /// Compute the new value from the current one, on the thread owning the property.
+    pub fn update<F: FnOnce(&T) -> T + Send + 'static>(&self, f: F) {
+        if let Some(shared) = self.handle.shared.upgrade() {
+            let f: UpdateFn<T> = Box::new(f);
+            shared.post(self.handle.id, Box::new(f));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::properties::RcProperty;
+    use std::cell::Cell;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_setter() {
+        let notified = Cell::new(0);
+        let queue = ThreadQueue::new();
+        let text = RcProperty::from(String::new());
+        let len = RcProperty::from_binding(|| text.get().len());
+        let _c = text.on_notify(|_| notified.set(notified.get() + 1));
+        let setter = queue.setter(text.property());
+        std::thread::spawn(move || {
+            setter.set("hello".to_owned());
+            setter.update(|s| format!("{} world", s));
+        })
+        .join()
+        .unwrap();
+        assert_eq!(text.get(), "");
+        assert_eq!(queue.process(), 2);
+        assert_eq!(text.get(), "hello world");
+        assert_eq!(len.get(), 11);
+        assert_eq!(notified.get(), 1);
+        assert_eq!(queue.process(), 0);
+    }
+
+    #[test]
+    fn test_behavior() {
+        use crate::animation::{AnimationDriver, Behavior, ManualClock};
+        let clock = Rc::new(ManualClock::default());
+        let driver = AnimationDriver::new(clock.clone());
+        let queue = ThreadQueue::new();
+        let x = RcProperty::from(0.);
+        let _behavior = Behavior::new(&driver, x.property());
+        queue.setter(x.property()).set(100.);
+        assert_eq!(queue.process(), 1);
+        // Animated from the current value
+        assert_eq!(x.get(), 0.);
+        assert!(driver.is_running());
+    }
+
+    #[test]
+    fn test_many_threads() {
+        let queue = ThreadQueue::new();
+        let counter = RcProperty::from(0usize);
+        let double = RcProperty::from_binding(|| counter.get() * 2);
+        let wakeups = Arc::new(AtomicUsize::new(0));
+        let w = wakeups.clone();
+        queue.set_wakeup(move || {
+            w.fetch_add(1, Ordering::SeqCst);
+        });
+        let setter = queue.setter(counter.property());
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let setter = setter.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..1000 {
+                        setter.update(|v| v + 1);
+                    }
+                })
+            })
+            .collect();
+        // Process while the threads are still posting
+        while counter.get() < 4000 {
+            queue.process();
+            assert_eq!(double.get(), counter.get() * 2);
+        }
+        for t in threads {
+            t.join().unwrap();
+        }
+        queue.process();
+        assert_eq!(counter.get(), 8000);
+        assert_eq!(double.get(), 16000);
+        assert!(wakeups.load(Ordering::SeqCst) >= 1);
+    }
+
+    #[test]
+    fn test_dropped_property() {
+        let queue = ThreadQueue::new();
+        let setter = {
+            let p = RcProperty::from(1);
+            queue.setter(p.property())
+        };
+        setter.set(2);
+        assert_eq!(queue.process(), 1);
+        assert!(queue.targets.borrow()[0].is_none());
+        std::mem::drop(queue);
+        setter.set(3);
+    }
+
+    #[test]
+    fn test_reuse_id() {
+        let queue = ThreadQueue::new();
+        let a = RcProperty::from(1);
+        let b = RcProperty::from(String::new());
+        for i in 0..10 {
+            let setter = queue.setter(a.property());
+            let other = setter.clone();
+            setter.set(i);
+            std::mem::drop(setter);
+            other.update(|v| v * 2);
+            std::mem::drop(other);
+            assert_eq!(queue.process(), 2);
+            assert_eq!(a.get(), i * 2);
+        }
+        let setter = queue.setter(b.property());
+        setter.set("hello".to_owned());
+        queue.process();
+        assert_eq!(b.get(), "hello");
+        assert_eq!(queue.targets.borrow().len(), 1);
+    }
+}
@@ -2,12 +2,17 @@
 //! This is unsafe because the node need to stay at the same location in memory.
 //! This was implemented before Pin was introduced. It could be mase safer uing Pin
 
+use alloc::boxed::Box;
 use core::ptr;
 use core::ptr::NonNull;
 
 pub trait LinkedList {
     type NodeItem;
     unsafe fn next_ptr(node: NonNull<Self::NodeItem>) -> NonNull<Node<Self>>;
+    /// Free a node which was allocated with Box, when the list is cleared.
+    unsafe fn free(node: NonNull<Self::NodeItem>) {
+        core::mem::drop(Box::from_raw(node.as_ptr()));
+    }
 }
 
 pub struct Node<L: LinkedList + ?Sized> {
@@ -24,8 +29,13 @@ impl<L: LinkedList + ?Sized> Default for Node<L> {
     }
 }
 
-impl<L: LinkedList + ?Sized> Drop for Node<L> {
-    fn drop(&mut self) {
+impl<L: LinkedList + ?Sized> Node<L> {
+    pub fn is_linked(&self) -> bool {
+        !self.prev.is_null()
+    }
+
+    /// Remove the node from the list it is in, if any.
+    pub fn unlink(&mut self) {
         if self.prev.is_null() {
             return;
         }
@@ -35,10 +45,18 @@ impl<L: LinkedList + ?Sized> Drop for Node<L> {
             }
             *self.prev = self.next;
         }
+        self.prev = ptr::null_mut();
+        self.next = ptr::null_mut();
     }
 }
 
-struct NodeIter<L: LinkedList + ?Sized>(*mut L::NodeItem);
+impl<L: LinkedList + ?Sized> Drop for Node<L> {
+    fn drop(&mut self) {
+        self.unlink();
+    }
+}
+
+pub struct NodeIter<L: LinkedList + ?Sized>(*mut L::NodeItem);
 
 impl<L: LinkedList + ?Sized> Iterator for NodeIter<L> {
     type Item = NonNull<L::NodeItem>;
@@ -55,11 +73,15 @@ pub struct Head<L: LinkedList + ?Sized>(*mut L::NodeItem);
 
 impl<L: LinkedList + ?Sized> Default for Head<L> {
     fn default() -> Self {
-        Head(ptr::null_mut())
+        Self::new()
     }
 }
 
 impl<L: LinkedList + ?Sized> Head<L> {
+    pub const fn new() -> Self {
+        Head(ptr::null_mut())
+    }
+
     pub unsafe fn append(&mut self, node: NonNull<L::NodeItem>) {
         let mut node_node = L::next_ptr(node);
         node_node.as_mut().next = self.0;
@@ -72,26 +94,41 @@ impl<L: LinkedList + ?Sized> Head<L> {
     }
 
     // Not safe because it breaks if the list is modified while iterating.
-    fn iter(&mut self) -> NodeIter<L> {
+    pub fn iter(&mut self) -> NodeIter<L> {
         NodeIter(self.0)
     }
 
     pub fn swap(&mut self, other: &mut Self) {
         unsafe {
-            ::std::mem::swap(&mut self.0, &mut other.0);
+            core::mem::swap(&mut self.0, &mut other.0);
             if !self.0.is_null() {
-                L::next_ptr(NonNull::new_unchecked(self.0)).as_mut().prev = self.0 as *mut _;
+                L::next_ptr(NonNull::new_unchecked(self.0)).as_mut().prev = &mut self.0 as *mut _;
             }
             if !other.0.is_null() {
-                L::next_ptr(NonNull::new_unchecked(other.0)).as_mut().prev = other.0 as *mut _;
+                L::next_ptr(NonNull::new_unchecked(other.0)).as_mut().prev = &mut other.0 as *mut _;
             }
         }
     }
 
+    /// Remove the first node from the list, without dropping it.
+    pub fn take_first(&mut self) -> Option<NonNull<L::NodeItem>> {
+        let n = NonNull::new(self.0)?;
+        unsafe {
+            let mut node_node = L::next_ptr(n);
+            self.0 = node_node.as_ref().next;
+            if !self.0.is_null() {
+                L::next_ptr(NonNull::new_unchecked(self.0)).as_mut().prev = &mut self.0 as *mut _;
+            }
+            node_node.as_mut().prev = ptr::null_mut();
+            node_node.as_mut().next = ptr::null_mut();
+        }
+        Some(n)
+    }
+
     pub fn clear(&mut self) {
         unsafe {
-            for x in self.iter() {
-                Box::from_raw(x.as_ptr());
+            while let Some(x) = self.take_first() {
+                L::free(x);
             }
         }
     }
@@ -100,16 +137,8 @@ impl<L: LinkedList + ?Sized> Head<L> {
 impl<L: LinkedList + ?Sized> Iterator for Head<L> {
     type Item = Box<L::NodeItem>;
     fn next(&mut self) -> Option<Self::Item> {
-        NonNull::new(self.0).map(|n| unsafe {
-            let mut node_node = L::next_ptr(n);
-            self.0 = node_node.as_ref().next;
-            if !self.0.is_null() {
-                L::next_ptr(NonNull::new_unchecked(self.0)).as_mut().prev = &mut self.0 as *mut _;
-            }
-            node_node.as_mut().prev = ::std::ptr::null_mut();
-            node_node.as_mut().next = ::std::ptr::null_mut();
-            Box::from_raw(n.as_ptr())
-        })
+        self.take_first()
+            .map(|n| unsafe { Box::from_raw(n.as_ptr()) })
     }
 }
 
@@ -122,6 +151,8 @@ impl<L: LinkedList + ?Sized> Drop for Head<L> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use alloc::vec;
+    use alloc::vec::Vec;
     enum TestList {}
     #[derive(Default)]
     struct TestNode {
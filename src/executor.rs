@@ -0,0 +1,394 @@
+//! Binding properties to futures, with a minimal single threaded executor.
+//!
+//! The futures run on the thread which spawned them, when `run_pending` is called on that
+//! thread. The function given to `set_wakeup` is called, possibly from another thread, when a
+//! task becomes ready while none was: it should schedule a call to `run_pending`, for example by
+//! posting an event to the event loop. `quick::show_window` does that for the Qt event loop.
+//!
+//! These functions use an executor for the `'static` futures of the thread. An `Executor`
+//! can also be created to run futures borrowing shorter lived data, such as the ones binding
+//! a `Property<'a, T>`. Its owner then calls its own `run_pending`.
+
+use crate::properties::{Property, RcProperty};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex, Weak};
+use std::task::{Context, Wake, Waker};
+
+type Task<'a> = Pin<Box<dyn Future<Output = ()> + 'a>>;
+
+#[derive(Default)]
+struct Shared {
+    ready: Mutex<Vec<usize>>,
+    wakeup: Mutex<Option<Arc<dyn Fn() + Send + Sync>>>,
+}
+
+impl Shared {
+    fn schedule(&self, id: usize) {
+        let was_empty = {
+            let mut ready = self.ready.lock().unwrap();
+            ready.push(id);
+            ready.len() == 1
+        };
+        if was_empty {
+            // Don't call it with the lock held
+            let wakeup = self.wakeup.lock().unwrap().clone();
+            if let Some(wakeup) = wakeup {
+                wakeup();
+            }
+        }
+    }
+}
+
+struct TaskWaker {
+    id: usize,
+    shared: Weak<Shared>,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        if let Some(shared) = self.shared.upgrade() {
+            shared.schedule(self.id);
+        }
+    }
+}
+
+/// Runs futures on the thread owning it, when `run_pending` is called.
+/// See the module documentation.
+#[derive(Default)]
+pub struct Executor<'a> {
+    shared: Arc<Shared>,
+    /// The future is None while it is being polled
+    tasks: RefCell<HashMap<usize, Option<Task<'a>>>>,
+    next_id: Cell<usize>,
+}
+
+thread_local!(static EXECUTOR: Rc<Executor<'static>> = Executor::new());
+
+/// Cancels the task when dropped
+#[must_use]
+pub struct TaskHandle<'a> {
+    executor: std::rc::Weak<Executor<'a>>,
+    id: usize,
+}
+
+impl<'a> Drop for TaskHandle<'a> {
+    fn drop(&mut self) {
+        if let Some(e) = self.executor.upgrade() {
+            // The future is dropped outside of the borrow, it may own other handles
+            let removed = e.tasks.borrow_mut().remove(&self.id);
+            core::mem::drop(removed);
+        }
+    }
+}
+
+/// Run the future on this thread. It is polled for the first time by the next call to
+/// `run_pending`.
+pub fn spawn_local<F: Future<Output = ()> + 'static>(future: F) -> TaskHandle<'static> {
+    EXECUTOR.with(|e| e.spawn(future))
+}
+
+/// Poll the tasks of this thread which are ready. Returns the number of polls.
+pub fn run_pending() -> usize {
+    EXECUTOR.with(|e| e.run_pending())
+}
+
+/// Set the function called when a task of this thread becomes ready, see the module
+/// documentation.
+pub fn set_wakeup<F: Fn() + Send + Sync + 'static>(f: F) {
+    EXECUTOR.with(|e| e.set_wakeup(f));
+}
+
+impl<'a> Executor<'a> {
+    pub fn new() -> Rc<Self> {
+        Default::default()
+    }
+
+    /// Run the future. It is polled for the first time by the next call to `run_pending`.
+    pub fn spawn<F: Future<Output = ()> + 'a>(self: &Rc<Self>, future: F) -> TaskHandle<'a> {
+        let id = self.next_id.get();
+        self.next_id.set(id + 1);
+        self.tasks.borrow_mut().insert(id, Some(Box::pin(future)));
+        self.shared.schedule(id);
+        TaskHandle {
+            executor: Rc::downgrade(self),
+            id,
+        }
+    }
+
+    /// Poll the tasks which are ready. Returns the number of polls.
+    pub fn run_pending(self: &Rc<Self>) -> usize {
+        // In case a task drops the last reference to the executor
+        let e = self.clone();
+        let mut count = 0;
+        loop {
+            let ready = std::mem::take(&mut *e.shared.ready.lock().unwrap());
+            if ready.is_empty() {
+                return count;
+            }
+            for id in ready {
+                let task = e.tasks.borrow_mut().get_mut(&id).and_then(Option::take);
+                let mut task = match task {
+                    Some(task) => task,
+                    // Cancelled, or already completed
+                    None => continue,
+                };
+                let waker = Waker::from(Arc::new(TaskWaker {
+                    id,
+                    shared: Arc::downgrade(&e.shared),
+                }));
+                count += 1;
+                let done = task
+                    .as_mut()
+                    .poll(&mut Context::from_waker(&waker))
+                    .is_ready();
+                let finished = {
+                    let mut tasks = e.tasks.borrow_mut();
+                    match tasks.get_mut(&id) {
+                        Some(slot) if !done => {
+                            *slot = Some(task);
+                            None
+                        }
+                        Some(_) => {
+                            tasks.remove(&id);
+                            Some(task)
+                        }
+                        // Cancelled while it was polled
+                        None => Some(task),
+                    }
+                };
+                core::mem::drop(finished);
+            }
+        }
+    }
+
+    /// Set the function called when a task becomes ready while none was, possibly from
+    /// another thread. It should schedule a call to `run_pending`.
+    pub fn set_wakeup<F: Fn() + Send + Sync + 'static>(&self, f: F) {
+        *self.shared.wakeup.lock().unwrap() = Some(Arc::new(f));
+    }
+}
+
+impl<T: Default + Clone + 'static> Property<'static, T> {
+    /// Bind the property to the result of a future.
+    ///
+    /// `f` creates the future, and it is called again as soon as the properties it accesses
+    /// change. The previous future is then cancelled if it did not complete. Only the properties
+    /// accessed by `f` itself are tracked, not the ones accessed by the future.
+    /// The property keeps its previous value until the future completes.
+    /// The futures run on the executor of the thread, see `set_binding_async_in` for the
+    /// properties which are not `'static`.
+    pub fn set_binding_async<F, Fut>(self: Pin<&Self>, f: F)
+    where
+        F: Fn() -> Fut + 'static,
+        Fut: Future<Output = T> + 'static,
+    {
+        EXECUTOR.with(|e| self.set_binding_async_impl(e, None, f))
+    }
+
+    /// Same as set_binding_async, but the property has the `pending` value until the future
+    /// completes.
+    pub fn set_binding_async_with_pending<F, Fut>(self: Pin<&Self>, pending: T, f: F)
+    where
+        F: Fn() -> Fut + 'static,
+        Fut: Future<Output = T> + 'static,
+    {
+        EXECUTOR.with(|e| self.set_binding_async_impl(e, Some(pending), f))
+    }
+}
+
+impl<'a, T: Default + Clone + 'a> Property<'a, T> {
+    /// Same as set_binding_async, with the futures run by this executor.
+    pub fn set_binding_async_in<F, Fut>(self: Pin<&Self>, executor: &Rc<Executor<'a>>, f: F)
+    where
+        F: Fn() -> Fut + 'a,
+        Fut: Future<Output = T> + 'a,
+    {
+        self.set_binding_async_impl(executor, None, f)
+    }
+
+    /// Same as set_binding_async_with_pending, with the futures run by this executor.
+    pub fn set_binding_async_with_pending_in<F, Fut>(
+        self: Pin<&Self>,
+        executor: &Rc<Executor<'a>>,
+        pending: T,
+        f: F,
+    ) where
+        F: Fn() -> Fut + 'a,
+        Fut: Future<Output = T> + 'a,
+    {
+        self.set_binding_async_impl(executor, Some(pending), f)
+    }
+
+    fn set_binding_async_impl<F, Fut>(
+        self: Pin<&Self>,
+        executor: &Rc<Executor<'a>>,
+        pending: Option<T>,
+        f: F,
+    ) where
+        F: Fn() -> Fut + 'a,
+        Fut: Future<Output = T> + 'a,
+    {
+        let executor = executor.clone();
+        // The value of the last future which completed, with its generation
+        let resolved = RcProperty::<(u64, T)>::default();
+        let weak_resolved = resolved.as_weak();
+        let generation = Rc::new(Cell::new(0));
+        let task = RefCell::new(None);
+        // Restarts the future when the dependencies of `f` change, and returns its generation
+        let request = RcProperty::from_binding(move || {
+            let future = f();
+            let gen = generation.get() + 1;
+            generation.set(gen);
+            let (weak_resolved, generation) = (weak_resolved.clone(), generation.clone());
+            // Replacing the handle cancels the previous future
+            *task.borrow_mut() = Some(executor.spawn(async move {
+                let value = future.await;
+                // In case it completed while a newer one was being started
+                if generation.get() == gen {
+                    weak_resolved.set((gen, value));
+                }
+            }));
+            gen
+        });
+        // Evaluated when its dependencies change, so that the stale future is cancelled even
+        // if nobody reads the property.
+        request.property().d().set_eager(true);
+        self.set_binding(move || {
+            let gen = request.get();
+            let (resolved_gen, value) = resolved.get();
+            match &pending {
+                Some(pending) if resolved_gen != gen => pending.clone(),
+                _ => value,
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::channel::oneshot;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_spawn() {
+        let woken = Arc::new(AtomicUsize::new(0));
+        let woken2 = woken.clone();
+        set_wakeup(move || {
+            woken2.fetch_add(1, Ordering::SeqCst);
+        });
+        let (tx, rx) = oneshot::channel();
+        let result = Rc::new(Cell::new(0));
+        let result2 = result.clone();
+        let _handle = spawn_local(async move { result2.set(rx.await.unwrap()) });
+        assert_eq!(woken.load(Ordering::SeqCst), 1);
+        assert_eq!(run_pending(), 1);
+        assert_eq!(run_pending(), 0);
+        tx.send(42).unwrap();
+        assert_eq!(woken.load(Ordering::SeqCst), 2);
+        assert_eq!(run_pending(), 1);
+        assert_eq!(result.get(), 42);
+
+        let (tx, rx) = oneshot::channel::<u32>();
+        let handle = spawn_local(async move {
+            rx.await.ok();
+        });
+        core::mem::drop(handle);
+        assert!(tx.is_canceled());
+        assert_eq!(run_pending(), 0);
+    }
+
+    #[test]
+    fn test_binding_async() {
+        let input = RcProperty::from(1);
+        let senders = Rc::new(RefCell::new(Vec::new()));
+        let output = RcProperty::default();
+        let (weak_input, senders2) = (input.as_weak(), senders.clone());
+        output
+            .property()
+            .set_binding_async_with_pending(-1, move || {
+                let (tx, rx) = oneshot::channel();
+                senders2.borrow_mut().push(tx);
+                let x = weak_input.get().unwrap();
+                async move { rx.await.unwrap_or(0) + x }
+            });
+        assert_eq!(output.get(), -1);
+        run_pending();
+        senders.borrow_mut().remove(0).send(10).unwrap();
+        run_pending();
+        assert_eq!(output.get(), 11);
+
+        input.set(2);
+        assert_eq!(output.get(), -1);
+        input.set(3);
+        assert_eq!(output.get(), -1);
+        // The future for 2 was cancelled
+        assert!(senders.borrow()[0].is_canceled());
+        run_pending();
+        senders.borrow_mut().pop().unwrap().send(100).unwrap();
+        run_pending();
+        assert_eq!(output.get(), 103);
+
+        // Cancelled even if the property is not read
+        input.set(5);
+        input.set(6);
+        assert!(senders.borrow()[senders.borrow().len() - 2].is_canceled());
+        run_pending();
+        senders.borrow_mut().pop().unwrap().send(100).unwrap();
+        run_pending();
+        assert_eq!(output.get(), 106);
+
+        // Dropping the binding cancels the future
+        input.set(4);
+        assert_eq!(output.get(), -1);
+        output.set(0);
+        assert!(senders.borrow().last().unwrap().is_canceled());
+    }
+
+    #[test]
+    fn test_binding_async_in() {
+        let offset = 10;
+        let input = RcProperty::from(1);
+        let executor = Executor::new();
+        let output = RcProperty::default();
+        // Borrows local variables, so it cannot run on the executor of the thread
+        output.property().set_binding_async_in(&executor, || {
+            let (x, offset) = (input.get(), &offset);
+            async move { x + *offset }
+        });
+        assert_eq!(output.get(), 0);
+        assert_eq!(executor.run_pending(), 1);
+        assert_eq!(output.get(), 11);
+        input.set(2);
+        assert_eq!(executor.run_pending(), 1);
+        assert_eq!(output.get(), 12);
+        assert_eq!(run_pending(), 0);
+    }
+
+    #[test]
+    fn test_binding_async_stale() {
+        let input = RcProperty::from(1);
+        let senders = Rc::new(RefCell::new(Vec::new()));
+        let output = RcProperty::default();
+        let (weak_input, senders2) = (input.as_weak(), senders.clone());
+        output.property().set_binding_async(move || {
+            let (tx, rx) = oneshot::channel();
+            senders2.borrow_mut().push(tx);
+            let x = weak_input.get().unwrap();
+            async move { rx.await.unwrap_or(0) + x }
+        });
+        run_pending();
+        // The output was never read, the first future is still cancelled
+        input.set(2);
+        assert!(senders.borrow_mut().remove(0).send(100).is_err());
+        run_pending();
+        assert_eq!(output.get(), 0);
+        senders.borrow_mut().remove(0).send(10).unwrap();
+        run_pending();
+        assert_eq!(output.get(), 12);
+    }
+}
@@ -0,0 +1,287 @@
+//! Introspection of the dependency graph, for debugging.
+//!
+//! A `Graph` is built by adding the properties of interest. The properties they depend on,
+//! and the ones depending on them, are discovered by walking the dependency links. Only the
+//! properties which were added explicitly have a name and a value.
+
+use super::{NotificationReciever, Property, PropertyBase};
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{Debug, Write};
+use core::pin::Pin;
+use core::ptr::NonNull;
+
+/// Identifies a node of the graph. (This is its address)
+pub type NodeId = usize;
+
+#[derive(Clone, Debug, Default)]
+pub struct GraphNode {
+    pub id: NodeId,
+    /// The name given to `Graph::add`, empty for the nodes which were discovered
+    pub name: String,
+    /// The description of the binding, if any
+    pub description: String,
+    /// The value, formatted with Debug
+    pub value: Option<String>,
+    pub has_binding: bool,
+    /// False for a ChangeEvent
+    pub is_property: bool,
+}
+
+impl GraphNode {
+    /// The name, or the description, or the address.
+    pub fn label(&self) -> String {
+        if !self.name.is_empty() {
+            self.name.clone()
+        } else if !self.description.is_empty() {
+            self.description.clone()
+        } else {
+            format!("{:#x}", self.id)
+        }
+    }
+}
+
+/// The dependency graph of some properties.
+#[derive(Default)]
+pub struct Graph {
+    nodes: BTreeMap<NodeId, GraphNode>,
+    /// (dependency, dependent): the second one is notified when the first one changes
+    edges: BTreeSet<(NodeId, NodeId)>,
+}
+
+fn id_of<T: ?Sized>(p: *const T) -> NodeId {
+    p as *const () as usize
+}
+
+impl Graph {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Add a property with its value, and all the properties connected to it.
+    pub fn add<T: Debug>(&mut self, name: &str, p: Pin<&Property<T>>) {
+        self.add_with(name, p, |v| format!("{:?}", v));
+    }
+
+    /// Same as add, with a custom formatting of the value.
+    pub fn add_with<T, F: Fn(&T) -> String>(
+        &mut self,
+        name: &str,
+        p: Pin<&Property<T>>,
+        format: F,
+    ) {
+        // The value is not re-evaluated, we want to see the graph as it is
+        let value = format(unsafe { &*p.value.get() });
+        self.add_impl(name, &*p, Some(value));
+    }
+
+    /// Same as add, for the types which do not implement Debug.
+    pub fn add_opaque<T>(&mut self, name: &str, p: Pin<&Property<T>>) {
+        self.add_impl(name, &*p, None);
+    }
+
+    fn add_impl(&mut self, name: &str, p: &dyn PropertyBase, value: Option<String>) {
+        let id = id_of(p);
+        self.visit(p);
+        let node = self.nodes.get_mut(&id).unwrap();
+        node.name = name.into();
+        node.value = value;
+    }
+
+    /// Add the property and all the ones connected to it, if it was not already there.
+    fn visit(&mut self, p: &dyn PropertyBase) {
+        let mut queue = alloc::vec![NonNull::from(p)];
+        while let Some(p) = queue.pop() {
+            let p = unsafe { p.as_ref() };
+            let id = id_of(p);
+            if self.nodes.contains_key(&id) {
+                continue;
+            }
+            self.nodes.insert(
+                id,
+                GraphNode {
+                    id,
+                    description: p.description(),
+                    has_binding: p.has_binding(),
+                    is_property: true,
+                    ..Default::default()
+                },
+            );
+            let edges = &mut self.edges;
+            p.visit_dependencies(&mut |dep| {
+                edges.insert((id_of(dep.as_ptr()), id));
+                queue.push(dep);
+            });
+            let nodes = &mut self.nodes;
+            p.visit_dependents(&mut |elem: NonNull<dyn NotificationReciever>| {
+                let elem = unsafe { elem.as_ref() };
+                edges.insert((id, id_of(elem)));
+                match elem.as_property() {
+                    Some(dependent) => queue.push(NonNull::from(dependent)),
+                    None => {
+                        nodes.entry(id_of(elem)).or_insert_with(|| GraphNode {
+                            id: id_of(elem),
+                            description: "ChangeEvent".into(),
+                            ..Default::default()
+                        });
+                    }
+                }
+            });
+        }
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = &GraphNode> {
+        self.nodes.values()
+    }
+
+    pub fn node(&self, id: NodeId) -> Option<&GraphNode> {
+        self.nodes.get(&id)
+    }
+
+    /// The id of the property, to be used with `node`, `dependencies` or `dependents`.
+    pub fn id<T>(p: Pin<&Property<T>>) -> NodeId {
+        id_of(&*p)
+    }
+
+    /// The nodes that `id` depends on.
+    pub fn dependencies(&self, id: NodeId) -> impl Iterator<Item = &GraphNode> {
+        self.edges
+            .iter()
+            .filter(move |e| e.1 == id)
+            .filter_map(move |e| self.nodes.get(&e.0))
+    }
+
+    /// The nodes which depend on `id`.
+    pub fn dependents(&self, id: NodeId) -> impl Iterator<Item = &GraphNode> {
+        self.edges
+            .iter()
+            .filter(move |e| e.0 == id)
+            .filter_map(move |e| self.nodes.get(&e.1))
+    }
+
+    /// Graphviz representation, the arrows go from a property to the ones depending on it.
+    pub fn to_dot(&self) -> String {
+        let mut r = String::from("digraph properties {\n");
+        for n in self.nodes.values() {
+            let mut label = n.label();
+            if let Some(v) = &n.value {
+                label = format!("{}\n= {}", label, v);
+            }
+            let shape = if !n.is_property {
+                "diamond"
+            } else if n.has_binding {
+                "box"
+            } else {
+                "ellipse"
+            };
+            let _ = writeln!(
+                r,
+                "    n{:x} [label={}, shape={}];",
+                n.id,
+                quote(&label),
+                shape
+            );
+        }
+        for (from, to) in &self.edges {
+            let _ = writeln!(r, "    n{:x} -> n{:x};", from, to);
+        }
+        r.push_str("}\n");
+        r
+    }
+
+    pub fn to_json(&self) -> String {
+        let nodes = self
+            .nodes
+            .values()
+            .map(|n| {
+                format!(
+                    "{{\"id\":{},\"name\":{},\"description\":{},\"value\":{},\"binding\":{},\"property\":{}}}",
+                    n.id,
+                    quote(&n.name),
+                    quote(&n.description),
+                    n.value.as_ref().map_or("null".into(), |v| quote(v)),
+                    n.has_binding,
+                    n.is_property
+                )
+            })
+            .collect::<Vec<_>>();
+        let edges = self
+            .edges
+            .iter()
+            .map(|(from, to)| format!("{{\"from\":{},\"to\":{}}}", from, to))
+            .collect::<Vec<_>>();
+        format!(
+            "{{\"nodes\":[{}],\"edges\":[{}]}}",
+            nodes.join(","),
+            edges.join(",")
+        )
+    }
+}
+
+/// Quote a string, escaped for both JSON and DOT
+fn quote(s: &str) -> String {
+    let mut r = String::with_capacity(s.len() + 2);
+    r.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => r.push_str("\\\""),
+            '\\' => r.push_str("\\\\"),
+            '\n' => r.push_str("\\n"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(r, "\\u{:04x}", c as u32);
+            }
+            c => r.push(c),
+        }
+    }
+    r.push('"');
+    r
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_graph() {
+        let a = Property::default();
+        let b = Property::default();
+        let c = Property::default();
+        pin_utils::pin_mut!(a);
+        pin_utils::pin_mut!(b);
+        pin_utils::pin_mut!(c);
+        let (a, b, c) = (a.as_ref(), b.as_ref(), c.as_ref());
+        a.set(1);
+        b.set_binding_owned(move || a.get() + 1);
+        c.set_binding_owned(move || a.get() + b.get());
+        assert_eq!(c.get(), 3);
+        let e = Box::pin(crate::properties_impl::ChangeEvent::new(|| {}));
+        e.as_ref().listen(c);
+
+        let mut graph = Graph::new();
+        graph.add("b", b);
+        assert_eq!(graph.nodes().count(), 4);
+        let node_b = graph.node(Graph::id(b)).unwrap();
+        assert_eq!(node_b.value.as_deref(), Some("2"));
+        assert!(node_b.has_binding);
+        let deps = |id| graph.dependencies(id).map(|n| n.id).collect::<Vec<_>>();
+        assert_eq!(deps(Graph::id(b)), vec![Graph::id(a)]);
+        let mut c_deps = deps(Graph::id(c));
+        c_deps.sort();
+        let mut expected = vec![Graph::id(a), Graph::id(b)];
+        expected.sort();
+        assert_eq!(c_deps, expected);
+        let c_dependents = graph.dependents(Graph::id(c)).collect::<Vec<_>>();
+        assert_eq!(c_dependents.len(), 1);
+        assert!(!c_dependents[0].is_property);
+
+        graph.add("a", a);
+        let dot = graph.to_dot();
+        assert!(dot.contains("label=\"a\\n= 1\""), "{}", dot);
+        assert!(dot.contains(&format!("n{:x} -> n{:x};", Graph::id(a), Graph::id(b))));
+        let json = graph.to_json();
+        assert!(json.contains("\"name\":\"b\",\"description\":\"\",\"value\":\"2\""));
+        assert_eq!(json.matches("\"from\"").count(), 4);
+    }
+}
@@ -1,92 +1,47 @@
 use super::*;
+use crate::collections::{ListChange, ListProperty};
+use crate::layout::layout_engine;
+pub use crate::layout::{Geometry, LayoutInfo};
 use qmetaobject::scenegraph::{ContainerNode, RectangleNode, SGNode, TransformNode};
-use qmetaobject::{QColor, QJSValue, QMetaType, QPointF, QQuickItem, QRectF, QString};
+use qmetaobject::{QColor, QJSValue, QMetaType, QPointF, QQuickItem, QString};
 use std::cell::RefCell;
 use std::ffi::CStr;
+use std::ops::Range;
 use std::os::raw::c_void;
+use std::pin::Pin;
 use std::rc::Rc;
 
-#[derive(Default)]
-pub struct Geometry<'a> {
-    pub x: Property<'a, f64>,
-    pub y: Property<'a, f64>,
-    pub width: Property<'a, f64>,
-    pub height: Property<'a, f64>,
-}
-impl<'a> Geometry<'a> {
-    pub fn width(&self) -> f64 {
-        self.width.get()
-    }
-    pub fn height(&self) -> f64 {
-        self.height.get()
-    }
-    pub fn left(&self) -> f64 {
-        self.x.get()
-    }
-    pub fn top(&self) -> f64 {
-        self.y.get()
-    }
-    pub fn right(&self) -> f64 {
-        self.x.get() + self.width.get()
-    }
-    pub fn bottom(&self) -> f64 {
-        self.y.get() + self.height.get()
-    }
-    pub fn vertical_center(&self) -> f64 {
-        self.x.get() + self.width.get() / 2.
-    }
-    pub fn horizontal_center(&self) -> f64 {
-        self.y.get() + self.height.get() / 2.
-    }
-
-    pub fn to_qrectf(&self) -> QRectF {
-        QRectF {
-            x: self.left(),
-            y: self.top(),
-            width: self.width(),
-            height: self.height(),
-        }
-    }
-}
-/*
-enum SizePolicy {
-    Fixed(f64),
-    Minimum(f64),
-    Maximum(f64)
-}*/
-
-pub struct LayoutInfo<'a> {
-    pub preferred_width: Property<'a, f64>,
-    pub preferred_height: Property<'a, f64>,
-    pub maximum_width: Property<'a, f64>,
-    pub maximum_height: Property<'a, f64>,
-    pub minimum_width: Property<'a, f64>,
-    pub minimum_height: Property<'a, f64>,
+/// The button of a mouse press or release
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+    Other,
 }
-impl<'a> Default for LayoutInfo<'a> {
-    fn default() -> Self {
-        LayoutInfo {
-            preferred_width: 0.0.into(),
-            preferred_height: 0.0.into(),
-            maximum_height: std::f64::MAX.into(),
-            maximum_width: std::f64::MAX.into(),
-            minimum_width: 0.0.into(),
-            minimum_height: 0.0.into(),
+impl MouseButton {
+    /// From a Qt::MouseButton value
+    pub fn from_qt(button: u32) -> Self {
+        match button {
+            0x1 => MouseButton::Left,
+            0x2 => MouseButton::Right,
+            0x4 => MouseButton::Middle,
+            _ => MouseButton::Other,
         }
     }
 }
 
 #[derive(Clone, Copy)]
 pub enum MouseEvent {
-    Press(QPointF),
-    Release(QPointF),
+    Press(QPointF, MouseButton),
+    Release(QPointF, MouseButton),
     Move(QPointF),
 }
 impl MouseEvent {
     fn position_ref(&mut self) -> &mut QPointF {
         match self {
-            MouseEvent::Press(ref mut x) => x,
-            MouseEvent::Release(ref mut x) => x,
+            MouseEvent::Press(ref mut x, _) => x,
+            MouseEvent::Release(ref mut x, _) => x,
             MouseEvent::Move(ref mut x) => x,
         }
     }
@@ -104,9 +59,11 @@ impl MouseEvent {
     }
 }
 
+/// The items are pinned in a Rc, and they contain an AlwaysPinned so that their properties
+/// can be accessed from `&self`.
 pub trait Item<'a> {
-    fn geometry(&self) -> &Geometry<'a>;
-    fn layout_info(&self) -> &LayoutInfo<'a>;
+    fn geometry(&self) -> Pin<&Geometry<'a>>;
+    fn layout_info(&self) -> Pin<&LayoutInfo<'a>>;
     fn update_paint_node(
         &self,
         node: SGNode<ContainerNode>,
@@ -118,20 +75,86 @@ pub trait Item<'a> {
     fn mouse_event(&self, _event: MouseEvent) -> bool {
         false
     }
+    /// Add the properties of this item and its children to the graph, for debugging.
+    fn add_to_graph(&self, name: &str, graph: &mut Graph) {
+        self.geometry()
+            .add_to_graph(&format!("{}.geometry", name), graph);
+        self.layout_info()
+            .add_to_graph(&format!("{}.layout_info", name), graph);
+    }
+}
+
+/// Register the item as the owner of its geometry and layout info, see `Property::set_owner`
+fn set_owner<'a, I: Item<'a> + 'a>(item: &Pin<Rc<I>>) {
+    item.geometry().set_owner(item);
+    item.layout_info().set_owner(item);
+}
+
+/// The children a container has bound to its geometry, following the changes of its list of
+/// children, so that it knows which ones were removed.
+#[derive(Default)]
+struct BoundChildren<'a>(RefCell<Vec<Pin<Rc<dyn Item<'a> + 'a>>>>);
+
+impl<'a> BoundChildren<'a> {
+    /// Apply the change, release the geometry of the removed children, and return the range of
+    /// the children to bind: the new ones and, if `by_index`, the ones whose index changed.
+    fn update(
+        &self,
+        children: Pin<&ListProperty<'a, Pin<Rc<dyn Item<'a> + 'a>>>>,
+        change: ListChange,
+        by_index: bool,
+    ) -> Range<usize> {
+        let mut bound = self.0.borrow_mut();
+        let item = |i: usize| children.with(|c| c[i].clone());
+        let (removed, range) = match change {
+            ListChange::Inserted(i) => {
+                bound.insert(i, item(i));
+                (Vec::new(), if by_index { i..bound.len() } else { i..i + 1 })
+            }
+            ListChange::Removed(i) => {
+                let removed = vec![bound.remove(i)];
+                (removed, if by_index { i..bound.len() } else { i..i })
+            }
+            ListChange::Moved(from, to) => {
+                let moved = bound.remove(from);
+                bound.insert(to, moved);
+                let range = from.min(to)..from.max(to) + 1;
+                (Vec::new(), if by_index { range } else { to..to })
+            }
+            ListChange::Updated(i) => (vec![std::mem::replace(&mut bound[i], item(i))], i..i + 1),
+            ListChange::Reset => {
+                let removed = std::mem::replace(&mut *bound, children.with(|c| c.to_vec()));
+                (removed, 0..bound.len())
+            }
+        };
+        drop(bound);
+        for x in removed {
+            // Keep the last geometry, without re-evaluating the bindings with the new indices
+            let g = x.geometry();
+            for p in [g.x(), g.y(), g.width(), g.height()].iter() {
+                p.remove_binding();
+            }
+        }
+        range
+    }
+
+    fn get(&self, range: Range<usize>) -> Vec<Pin<Rc<dyn Item<'a> + 'a>>> {
+        self.0.borrow()[range].to_vec()
+    }
 }
 
 pub trait ItemContainer<'a> {
-    fn add_child(&self, child: Rc<dyn Item<'a> + 'a>);
+    fn add_child(&self, child: Pin<Rc<dyn Item<'a> + 'a>>);
 }
 
 impl<'a, T, I: Item<'a> + 'a> Item<'a> for T
 where
     T: ::std::ops::Deref<Target = I>,
 {
-    fn geometry(&self) -> &Geometry<'a> {
+    fn geometry(&self) -> Pin<&Geometry<'a>> {
         ::std::ops::Deref::deref(self).geometry()
     }
-    fn layout_info(&self) -> &LayoutInfo<'a> {
+    fn layout_info(&self) -> Pin<&LayoutInfo<'a>> {
         ::std::ops::Deref::deref(self).layout_info()
     }
     fn update_paint_node(
@@ -147,111 +170,30 @@ where
     fn mouse_event(&self, event: MouseEvent) -> bool {
         ::std::ops::Deref::deref(self).mouse_event(event)
     }
-}
-
-mod layout_engine {
-
-    use std::ops::Add;
-
-    pub type Coord = f64;
-
-    #[derive(Default)]
-    pub struct ItemInfo {
-        pub min: Coord,
-        pub max: Coord,
-        pub preferred: Coord,
-        pub expand: usize,
-    }
-
-    impl<'a> Add<&'a ItemInfo> for ItemInfo {
-        type Output = ItemInfo;
-
-        fn add(self, other: &'a ItemInfo) -> ItemInfo {
-            ItemInfo {
-                min: self.min + other.min,
-                max: self.max + other.max, // the idea is that it saturate with the max value or infinity
-                preferred: self.preferred + other.preferred,
-                expand: self.expand + other.expand,
-            }
-        }
-    }
-
-    pub fn compute_total_info(info: &[ItemInfo], spacing: Coord) -> ItemInfo {
-        let mut sum: ItemInfo = info.iter().fold(ItemInfo::default(), Add::add);
-        let total_spacing = spacing * (info.len() - 1) as Coord;
-        sum.min += total_spacing;
-        sum.max += total_spacing;
-        sum.preferred += total_spacing;
-        sum
-    }
-
-    #[derive(Clone, Copy)]
-    pub struct ItemResult {
-        pub size: Coord,
-        pub pos: Coord,
-    }
-
-    pub fn do_layout(
-        info: &[ItemInfo],
-        total: ItemInfo,
-        spacing: Coord,
-        size: Coord,
-    ) -> Vec<ItemResult> {
-        // FIXME! consider maximum, or the case where we are smaller that the minimum
-        if size < total.preferred {
-            let to_remove = total.preferred - size;
-            let total_allowed_to_remove = total.preferred - total.min;
-
-            let mut pos = 0 as Coord;
-            info.iter()
-                .map(|it| {
-                    let s = it.preferred
-                        - (it.preferred - it.min) * to_remove / total_allowed_to_remove;
-                    let p = pos;
-                    pos += s + spacing;
-                    ItemResult { size: s, pos: p }
-                })
-                .collect()
-        } else {
-            let to_add = size - total.preferred;
-            //let total_allowed_to_add = total.max - preferred;
-
-            let mut pos = 0 as Coord;
-            info.iter()
-                .map(|it| {
-                    let s = if total.expand > 0 {
-                        it.preferred + to_add * it.expand as Coord / total.expand as Coord
-                    } else {
-                        it.preferred + to_add / info.len() as Coord
-                    };
-                    let p = pos;
-                    pos += s + spacing;
-                    ItemResult { size: s, pos: p }
-                })
-                .collect()
-        }
+    fn add_to_graph(&self, name: &str, graph: &mut Graph) {
+        ::std::ops::Deref::deref(self).add_to_graph(name, graph)
     }
-
 }
 
 macro_rules! declare_box_layout {
     ($ColumnLayout:ident, $x:ident, $width:ident, $minimum_width:ident, $maximum_width:ident, $preferred_width:ident,
         $y:ident, $height:ident, $minimum_height:ident, $maximum_height:ident, $preferred_height:ident) => {
-        #[derive(Default)]
         pub struct $ColumnLayout<'a> {
-            pub geometry: Geometry<'a>,
-            pub layout_info: LayoutInfo<'a>,
-            pub spacing: Property<'a, f64>,
+            geometry: Geometry<'a>,
+            layout_info: LayoutInfo<'a>,
+            spacing: Property<'a, f64>,
 
-            children: RefCell<Vec<Rc<dyn Item<'a> + 'a>>>,
+            children: ListProperty<'a, Pin<Rc<dyn Item<'a> + 'a>>>,
+            bound_children: BoundChildren<'a>,
             positions: Property<'a, Vec<layout_engine::ItemResult>>,
+            _pinned: AlwaysPinned,
         }
         impl<'a> Item<'a> for $ColumnLayout<'a> {
-            fn geometry(&self) -> &Geometry<'a> {
-                &self.geometry
+            fn geometry(&self) -> Pin<&Geometry<'a>> {
+                unsafe { Pin::new_unchecked(&self.geometry) }
             }
-            fn layout_info(&self) -> &LayoutInfo<'a> {
-                &self.layout_info
+            fn layout_info(&self) -> Pin<&LayoutInfo<'a>> {
+                unsafe { Pin::new_unchecked(&self.layout_info) }
             }
 
             fn update_paint_node(
@@ -263,8 +205,8 @@ macro_rules! declare_box_layout {
                 node.update_static(|mut n: SGNode<TransformNode>| -> SGNode<TransformNode> {
                     n.set_translation(g.left(), g.top());
                     n.update_sub_node(|mut node: SGNode<ContainerNode>| {
-                        node.update_dynamic(self.children.borrow().iter(), |i, n| {
-                            i.update_paint_node(n, item)
+                        self.children().with(|c| {
+                            node.update_dynamic(c.iter(), |i, n| i.update_paint_node(n, item))
                         });
                         node
                     });
@@ -274,13 +216,14 @@ macro_rules! declare_box_layout {
             }
 
             fn init(&self, item: &(dyn QQuickItem + 'a)) {
-                for i in self.children.borrow().iter() {
+                for i in self.children().to_vec() {
                     i.init(item);
                 }
             }
 
             fn mouse_event(&self, event: MouseEvent) -> bool {
-                for i in self.children.borrow().iter() {
+                // A copy, the handler may change the children
+                for i in self.children().to_vec() {
                     let g = i.geometry().to_qrectf();
                     if g.contains(event.position()) {
                         return i.mouse_event(event.translated(g.top_left()));
@@ -288,126 +231,155 @@ macro_rules! declare_box_layout {
                 }
                 return false;
             }
+
+            fn add_to_graph(&self, name: &str, graph: &mut Graph) {
+                self.geometry()
+                    .add_to_graph(&format!("{}.geometry", name), graph);
+                self.layout_info()
+                    .add_to_graph(&format!("{}.layout_info", name), graph);
+                self.spacing()
+                    .add_to_graph(&format!("{}.spacing", name), graph);
+                self.positions()
+                    .add_to_graph_opaque(&format!("{}.positions", name), graph);
+                for (idx, i) in self.children().to_vec().iter().enumerate() {
+                    i.add_to_graph(&format!("{}[{}]", name, idx), graph);
+                }
+            }
         }
 
-        impl<'a> ItemContainer<'a> for Rc<$ColumnLayout<'a>> {
-            fn add_child(&self, child: Rc<dyn Item<'a> + 'a>) {
-                self.children.borrow_mut().push(child);
-                $ColumnLayout::build_layout(self);
+        impl<'a> ItemContainer<'a> for Pin<Rc<$ColumnLayout<'a>>> {
+            fn add_child(&self, child: Pin<Rc<dyn Item<'a> + 'a>>) {
+                self.children().push(child);
             }
         }
 
         impl<'a> $ColumnLayout<'a> {
-            pub fn new() -> Rc<Self> {
-                Default::default()
+            pub fn new() -> Pin<Rc<Self>> {
+                let this = Rc::pin($ColumnLayout {
+                    geometry: Default::default(),
+                    layout_info: Default::default(),
+                    spacing: Default::default(),
+                    children: Default::default(),
+                    bound_children: Default::default(),
+                    positions: Default::default(),
+                    _pinned: unsafe { AlwaysPinned::new() },
+                });
+                set_owner(&this);
+                this.spacing().set_owner(&this);
+                this.children().set_owner(&this);
+                this.positions().set_owner(&this);
+                $ColumnLayout::build_layout(&this);
+                let w = PinWeak::downgrade(this.clone());
+                this.children().on_change(move |change| {
+                    if let Some(this) = w.upgrade() {
+                        $ColumnLayout::bind_children(&this, change);
+                    }
+                });
+                this
             }
 
-            fn build_layout(this: &Rc<Self>) {
+            pub fn spacing(&self) -> Pin<&Property<'a, f64>> {
+                unsafe { Pin::new_unchecked(&self.spacing) }
+            }
+
+            pub fn children(&self) -> Pin<&ListProperty<'a, Pin<Rc<dyn Item<'a> + 'a>>>> {
+                unsafe { Pin::new_unchecked(&self.children) }
+            }
+
+            fn positions(&self) -> Pin<&Property<'a, Vec<layout_engine::ItemResult>>> {
+                unsafe { Pin::new_unchecked(&self.positions) }
+            }
+
+            fn build_layout(this: &Pin<Rc<Self>>) {
                 // The minimum width is the max of the minimums
-                let w = Rc::downgrade(this);
-                this.layout_info.$minimum_width.set_binding(move || {
-                    w.upgrade().map_or(0., |x| {
-                        x.children
-                            .borrow()
-                            .iter()
-                            .map(|i| i.layout_info().$minimum_width.get())
-                            .fold(0., f64::max)
-                    })
+                let minimum = this.children().fold(0., |acc, i| {
+                    f64::max(acc, i.layout_info().$minimum_width().get())
                 });
+                this.layout_info()
+                    .$minimum_width()
+                    .set_binding(move || minimum.get());
 
                 // The minimum height is the sum of the minimums
-                let w = Rc::downgrade(this);
-                this.layout_info.$minimum_height.set_binding(move || {
-                    w.upgrade().map_or(0., |x| {
-                        x.children
-                            .borrow()
-                            .iter()
-                            .map(|i| i.layout_info().$minimum_height.get())
-                            .sum()
-                    })
-                });
+                let minimum = this
+                    .children()
+                    .fold(0., |acc, i| acc + i.layout_info().$minimum_height().get());
+                this.layout_info()
+                    .$minimum_height()
+                    .set_binding(move || minimum.get());
 
                 // The maximum width is the min of the maximums
-                let w = Rc::downgrade(this);
-                this.layout_info.$maximum_width.set_binding(move || {
-                    w.upgrade().map_or(0., |x| {
-                        x.children
-                            .borrow()
-                            .iter()
-                            .map(|i| i.layout_info().$maximum_width.get())
-                            .fold(std::f64::MAX, f64::min)
-                    })
+                let maximum = this.children().fold(std::f64::MAX, |acc, i| {
+                    f64::min(acc, i.layout_info().$maximum_width().get())
                 });
+                this.layout_info()
+                    .$maximum_width()
+                    .set_binding(move || maximum.get());
                 // The maximum height is the sum of the maximums (assume it saturates)
-                let w = Rc::downgrade(this);
-                this.layout_info.$maximum_height.set_binding(move || {
-                    w.upgrade().map_or(0., |x| {
-                        x.children
-                            .borrow()
-                            .iter()
-                            .map(|i| i.layout_info().$maximum_height.get())
-                            .sum()
-                    })
-                });
+                let maximum = this
+                    .children()
+                    .fold(0., |acc, i| acc + i.layout_info().$maximum_height().get());
+                this.layout_info()
+                    .$maximum_height()
+                    .set_binding(move || maximum.get());
 
                 // preferred width is the minimum width
-                let w = Rc::downgrade(this);
-                this.layout_info.$preferred_width.set_binding(Some(move || {
-                    Some(w.upgrade()?.layout_info.$minimum_width.get())
-                }));
+                let w = PinWeak::downgrade(this.clone());
+                this.layout_info()
+                    .$preferred_width()
+                    .set_binding(Some(move || {
+                        Some(w.upgrade()?.layout_info().$minimum_width().get())
+                    }));
 
                 // preferred height is the sum of preferred height
-                let w = Rc::downgrade(this);
-                this.layout_info.$preferred_height.set_binding(move || {
-                    w.upgrade().map_or(0., |x| {
-                        x.children
-                            .borrow()
-                            .iter()
-                            .map(|i| i.layout_info().$preferred_height.get())
-                            .sum()
-                    })
-                });
+                let preferred = this
+                    .children()
+                    .fold(0., |acc, i| acc + i.layout_info().$preferred_height().get());
+                this.layout_info()
+                    .$preferred_height()
+                    .set_binding(move || preferred.get());
 
                 // Set the positions
-                let w = Rc::downgrade(this);
-                this.positions.set_binding(move || {
+                let w = PinWeak::downgrade(this.clone());
+                this.positions().set_binding(move || {
                     w.upgrade().map_or(Vec::default(), |w| {
-                        let v = w
-                            .children
-                            .borrow()
-                            .iter()
-                            .map(|x| {
-                                layout_engine::ItemInfo {
-                                    min: x.layout_info().$minimum_height.get(),
-                                    max: x.layout_info().$maximum_height.get(),
-                                    preferred: x.layout_info().$preferred_height.get(),
-                                    expand: 1, // FIXME
-                                }
-                            })
-                            .collect::<Vec<_>>();
+                        let v = w.children().with(|c| {
+                            c.iter()
+                                .map(|x| {
+                                    layout_engine::ItemInfo {
+                                        min: x.layout_info().$minimum_height().get(),
+                                        max: x.layout_info().$maximum_height().get(),
+                                        preferred: x.layout_info().$preferred_height().get(),
+                                        expand: 1, // FIXME
+                                    }
+                                })
+                                .collect::<Vec<_>>()
+                        });
                         layout_engine::do_layout(
                             &v,
                             layout_engine::compute_total_info(&v, 0.),
                             0.,
-                            w.geometry.$height(),
+                            w.geometry().$height().get(),
                         )
                     })
                 });
+            }
 
-                // Set the sizes
-                for (idx, x) in this.children.borrow().iter().enumerate() {
-                    let w = Rc::downgrade(this);
+            /// Bind the geometry of the children to their position in the layout
+            fn bind_children(this: &Pin<Rc<Self>>, change: ListChange) {
+                let range = this.bound_children.update(this.children(), change, true);
+                for (idx, x) in range.clone().zip(this.bound_children.get(range)) {
+                    let w = PinWeak::downgrade(this.clone());
                     x.geometry()
-                        .$width
-                        .set_binding(Some(move || Some(w.upgrade()?.geometry().$width())));
-                    x.geometry().$x.set_binding(|| 0.);
-                    let w = Rc::downgrade(this);
-                    x.geometry().$height.set_binding(Some(move || {
-                        Some(w.upgrade()?.positions.get().get(idx)?.size)
+                        .$width()
+                        .set_binding(Some(move || Some(w.upgrade()?.geometry().$width().get())));
+                    x.geometry().$x().set_binding(|| 0.);
+                    let w = PinWeak::downgrade(this.clone());
+                    x.geometry().$height().set_binding(Some(move || {
+                        w.upgrade()?.positions().with(|p| Some(p.get(idx)?.size))
                     }));
-                    let w = Rc::downgrade(this);
-                    x.geometry().$y.set_binding(Some(move || {
-                        Some(w.upgrade()?.positions.get().get(idx)?.pos)
+                    let w = PinWeak::downgrade(this.clone());
+                    x.geometry().$y().set_binding(Some(move || {
+                        w.upgrade()?.positions().with(|p| Some(p.get(idx)?.pos))
                     }));
                 }
             }
@@ -445,56 +417,69 @@ declare_box_layout!(
 
 #[test]
 fn test_layout() {
-    #[derive(Default)]
     pub struct LItem<'a> {
         geometry: Geometry<'a>,
         layout_info: LayoutInfo<'a>,
         width: Property<'a, f64>,
         height: Property<'a, f64>,
+        _pinned: AlwaysPinned,
     }
     impl<'a> Item<'a> for LItem<'a> {
-        fn geometry(&self) -> &Geometry<'a> {
-            &self.geometry
+        fn geometry(&self) -> Pin<&Geometry<'a>> {
+            unsafe { Pin::new_unchecked(&self.geometry) }
         }
-        fn layout_info(&self) -> &LayoutInfo<'a> {
-            &self.layout_info
+        fn layout_info(&self) -> Pin<&LayoutInfo<'a>> {
+            unsafe { Pin::new_unchecked(&self.layout_info) }
         }
     }
     impl<'a> LItem<'a> {
-        pub fn new() -> Rc<Self> {
-            let r = Rc::new(LItem::default());
-            let w = Rc::downgrade(&r);
-            r.layout_info
-                .minimum_height
-                .set_binding(move || w.upgrade().map_or(0., |w| w.height.get()));
-            let w = Rc::downgrade(&r);
-            r.layout_info
-                .preferred_height
-                .set_binding(move || w.upgrade().map_or(0., |w| w.height.get()));
-            let w = Rc::downgrade(&r);
-            r.layout_info
-                .maximum_height
-                .set_binding(move || w.upgrade().map_or(0., |w| w.height.get()));
-            let w = Rc::downgrade(&r);
-            r.layout_info
-                .minimum_width
-                .set_binding(move || w.upgrade().map_or(0., |w| w.width.get()));
-            let w = Rc::downgrade(&r);
-            r.layout_info
-                .preferred_width
-                .set_binding(move || w.upgrade().map_or(0., |w| w.width.get()));
-            let w = Rc::downgrade(&r);
-            r.layout_info
-                .maximum_width
-                .set_binding(move || w.upgrade().map_or(0., |w| w.width.get()));
+        pub fn new() -> Pin<Rc<Self>> {
+            let r = Rc::pin(LItem {
+                geometry: Default::default(),
+                layout_info: Default::default(),
+                width: Default::default(),
+                height: Default::default(),
+                _pinned: unsafe { AlwaysPinned::new() },
+            });
+            set_owner(&r);
+            let w = PinWeak::downgrade(r.clone());
+            r.layout_info()
+                .minimum_height()
+                .set_binding(move || w.upgrade().map_or(0., |w| w.height().get()));
+            let w = PinWeak::downgrade(r.clone());
+            r.layout_info()
+                .preferred_height()
+                .set_binding(move || w.upgrade().map_or(0., |w| w.height().get()));
+            let w = PinWeak::downgrade(r.clone());
+            r.layout_info()
+                .maximum_height()
+                .set_binding(move || w.upgrade().map_or(0., |w| w.height().get()));
+            let w = PinWeak::downgrade(r.clone());
+            r.layout_info()
+                .minimum_width()
+                .set_binding(move || w.upgrade().map_or(0., |w| w.width().get()));
+            let w = PinWeak::downgrade(r.clone());
+            r.layout_info()
+                .preferred_width()
+                .set_binding(move || w.upgrade().map_or(0., |w| w.width().get()));
+            let w = PinWeak::downgrade(r.clone());
+            r.layout_info()
+                .maximum_width()
+                .set_binding(move || w.upgrade().map_or(0., |w| w.width().get()));
             r
         }
+        pub fn width(&self) -> Pin<&Property<'a, f64>> {
+            unsafe { Pin::new_unchecked(&self.width) }
+        }
+        pub fn height(&self) -> Pin<&Property<'a, f64>> {
+            unsafe { Pin::new_unchecked(&self.height) }
+        }
     }
 
     let lay = rsml! {
         ColumnLayout {
-            geometry.width: ColumnLayout.layout_info.preferred_width.get(),
-            geometry.height: ColumnLayout.layout_info.preferred_height.get(),
+            geometry.width: ColumnLayout.layout_info().preferred_width().get(),
+            geometry.height: ColumnLayout.layout_info().preferred_height().get(),
         }
     };
 
@@ -504,8 +489,8 @@ fn test_layout() {
             height : 100.,
         }
     });
-    assert_eq!(lay.geometry.width(), 150.);
-    assert_eq!(lay.geometry.height(), 100.);
+    assert_eq!(lay.geometry().width().get(), 150.);
+    assert_eq!(lay.geometry().height().get(), 100.);
     let middle = rsml! {
         LItem {
             width : 110.,
@@ -519,34 +504,52 @@ fn test_layout() {
             height : 60.,
         }
     });
-    assert_eq!(lay.geometry.width(), 190.);
-    assert_eq!(lay.geometry.height(), 100. + 90. + 60.);
+    assert_eq!(lay.geometry().width().get(), 190.);
+    assert_eq!(lay.geometry().height().get(), 100. + 90. + 60.);
 
-    middle.width.set(200.);
-    middle.height.set(50.);
+    middle.width().set(200.);
+    middle.height().set(50.);
 
-    assert_eq!(lay.geometry.width(), 200.);
-    assert_eq!(lay.geometry.height(), 100. + 50. + 60.);
+    assert_eq!(lay.geometry().width().get(), 200.);
+    assert_eq!(lay.geometry().height().get(), 100. + 50. + 60.);
 
     assert_eq!(
-        lay.geometry.height(),
-        lay.children.borrow()[2].geometry().bottom()
+        lay.geometry().height().get(),
+        lay.children().get(2).unwrap().geometry().bottom()
     );
+    assert_eq!(middle.geometry().height().get(), 50.);
+    assert_eq!(middle.geometry().top(), 100.);
+
+    lay.children().remove(1);
+    assert_eq!(lay.geometry().height().get(), 100. + 60.);
+    assert_eq!(lay.children().get(1).unwrap().geometry().top(), 100.);
+    // The removed child is no longer bound to the layout
+    assert_eq!(middle.geometry().height().get(), 50.);
+    assert_eq!(middle.geometry().top(), 100.);
+
+    lay.children().move_item(0, 1);
+    assert_eq!(lay.children().get(0).unwrap().geometry().top(), 0.);
+    assert_eq!(lay.children().get(1).unwrap().geometry().top(), 60.);
+
+    lay.children().clear();
+    assert_eq!(lay.geometry().height().get(), 0.);
+    assert_eq!(middle.geometry().height().get(), 50.);
 }
 
 /// Can contains other Items, resize the items to the size of the Caintainer
-#[derive(Default)]
 pub struct Container<'a> {
-    pub geometry: Geometry<'a>,
-    pub layout_info: LayoutInfo<'a>,
-    children: RefCell<Vec<Rc<dyn Item<'a> + 'a>>>,
+    geometry: Geometry<'a>,
+    layout_info: LayoutInfo<'a>,
+    children: ListProperty<'a, Pin<Rc<dyn Item<'a> + 'a>>>,
+    bound_children: BoundChildren<'a>,
+    _pinned: AlwaysPinned,
 }
 impl<'a> Item<'a> for Container<'a> {
-    fn geometry(&self) -> &Geometry<'a> {
-        &self.geometry
+    fn geometry(&self) -> Pin<&Geometry<'a>> {
+        unsafe { Pin::new_unchecked(&self.geometry) }
     }
-    fn layout_info(&self) -> &LayoutInfo<'a> {
-        &self.layout_info
+    fn layout_info(&self) -> Pin<&LayoutInfo<'a>> {
+        unsafe { Pin::new_unchecked(&self.layout_info) }
     }
 
     fn update_paint_node(
@@ -558,9 +561,8 @@ impl<'a> Item<'a> for Container<'a> {
         node.update_static(|mut n: SGNode<TransformNode>| -> SGNode<TransformNode> {
             n.set_translation(g.left(), g.top());
             n.update_sub_node(|mut node: SGNode<ContainerNode>| {
-                node.update_dynamic(self.children.borrow().iter(), |i, n| {
-                    i.update_paint_node(n, item)
-                });
+                self.children()
+                    .with(|c| node.update_dynamic(c.iter(), |i, n| i.update_paint_node(n, item)));
                 node
             });
             n
@@ -569,70 +571,105 @@ impl<'a> Item<'a> for Container<'a> {
     }
 
     fn init(&self, item: &(dyn QQuickItem + 'a)) {
-        for i in self.children.borrow().iter() {
+        for i in self.children().to_vec() {
             i.init(item);
         }
     }
 
     fn mouse_event(&self, event: MouseEvent) -> bool {
         let mut ret = false;
-        for i in self.children.borrow().iter() {
+        for i in self.children().to_vec() {
             ret = ret || i.mouse_event(event);
         }
         ret
     }
+
+    fn add_to_graph(&self, name: &str, graph: &mut Graph) {
+        self.geometry()
+            .add_to_graph(&format!("{}.geometry", name), graph);
+        self.layout_info()
+            .add_to_graph(&format!("{}.layout_info", name), graph);
+        for (idx, i) in self.children().to_vec().iter().enumerate() {
+            i.add_to_graph(&format!("{}[{}]", name, idx), graph);
+        }
+    }
 }
 
-impl<'a> ItemContainer<'a> for Rc<Container<'a>> {
-    fn add_child(&self, child: Rc<dyn Item<'a> + 'a>) {
-        self.children.borrow_mut().push(child);
-        Container::build_layout(self);
+impl<'a> ItemContainer<'a> for Pin<Rc<Container<'a>>> {
+    fn add_child(&self, child: Pin<Rc<dyn Item<'a> + 'a>>) {
+        self.children().push(child);
     }
 }
 
 impl<'a> Container<'a> {
-    pub fn new() -> Rc<Self> {
-        Default::default()
+    pub fn new() -> Pin<Rc<Self>> {
+        let this = Rc::pin(Container {
+            geometry: Default::default(),
+            layout_info: Default::default(),
+            children: Default::default(),
+            bound_children: Default::default(),
+            _pinned: unsafe { AlwaysPinned::new() },
+        });
+        set_owner(&this);
+        this.children().set_owner(&this);
+        let w = PinWeak::downgrade(this.clone());
+        this.children().on_change(move |change| {
+            if let Some(this) = w.upgrade() {
+                Container::bind_children(&this, change);
+            }
+        });
+        this
     }
 
-    fn build_layout(this: &Rc<Self>) {
-        for x in this.children.borrow().iter() {
-            let w = Rc::downgrade(this);
+    pub fn children(&self) -> Pin<&ListProperty<'a, Pin<Rc<dyn Item<'a> + 'a>>>> {
+        unsafe { Pin::new_unchecked(&self.children) }
+    }
+
+    fn bind_children(this: &Pin<Rc<Self>>, change: ListChange) {
+        let range = this.bound_children.update(this.children(), change, false);
+        for x in this.bound_children.get(range) {
+            let w = PinWeak::downgrade(this.clone());
             x.geometry()
-                .width
-                .set_binding(Some(move || Some(w.upgrade()?.geometry().width())));
-            let w = Rc::downgrade(this);
+                .width()
+                .set_binding(Some(move || Some(w.upgrade()?.geometry().width().get())));
+            let w = PinWeak::downgrade(this.clone());
             x.geometry()
-                .height
-                .set_binding(Some(move || Some(w.upgrade()?.geometry().height())));
-            x.geometry().x.set(0.);
-            x.geometry().y.set(0.);
+                .height()
+                .set_binding(Some(move || Some(w.upgrade()?.geometry().height().get())));
+            x.geometry().x().set(0.);
+            x.geometry().y().set(0.);
         }
     }
 }
 
-#[derive(Default)]
 pub struct Rectangle<'a> {
-    pub geometry: Geometry<'a>,
-    pub layout_info: LayoutInfo<'a>,
-    pub color: Property<'a, QColor>,
+    geometry: Geometry<'a>,
+    layout_info: LayoutInfo<'a>,
+    color: Property<'a, QColor>,
+    update_connection: RefCell<Option<ScopedConnection<'a>>>,
+    _pinned: AlwaysPinned,
 }
 
 impl<'a> Item<'a> for Rectangle<'a> {
-    fn geometry(&self) -> &Geometry<'a> {
-        &self.geometry
+    fn geometry(&self) -> Pin<&Geometry<'a>> {
+        unsafe { Pin::new_unchecked(&self.geometry) }
     }
-    fn layout_info(&self) -> &LayoutInfo<'a> {
-        &self.layout_info
+    fn layout_info(&self) -> Pin<&LayoutInfo<'a>> {
+        unsafe { Pin::new_unchecked(&self.layout_info) }
     }
 
     fn init(&self, item: &(dyn QQuickItem + 'a)) {
         let item_ptr = qmetaobject::QPointer::<dyn QQuickItem>::from(item);
-        self.color.on_notify(move |_| {
-            if let Some(x) = item_ptr.as_ref() {
-                x.update()
-            };
-        });
+        // Replacing the connection disconnects the one from a previous call to init
+        *self.update_connection.borrow_mut() = Some(
+            self.color()
+                .on_notify(move |_| {
+                    if let Some(x) = item_ptr.as_ref() {
+                        x.update()
+                    };
+                })
+                .scoped(),
+        );
     }
 
     fn update_paint_node(
@@ -642,16 +679,37 @@ impl<'a> Item<'a> for Rectangle<'a> {
     ) -> SGNode<ContainerNode> {
         node.update_static(|mut n: SGNode<RectangleNode>| -> SGNode<RectangleNode> {
             n.create(item);
-            n.set_color(self.color.get());
-            n.set_rect(self.geometry.to_qrectf());
+            n.set_color(self.color().get());
+            n.set_rect(self.geometry().to_qrectf());
             n
         });
         node
     }
+    fn add_to_graph(&self, name: &str, graph: &mut Graph) {
+        self.geometry()
+            .add_to_graph(&format!("{}.geometry", name), graph);
+        self.layout_info()
+            .add_to_graph(&format!("{}.layout_info", name), graph);
+        self.color()
+            .add_to_graph_opaque(&format!("{}.color", name), graph);
+    }
 }
 impl<'a> Rectangle<'a> {
-    pub fn new() -> Rc<Self> {
-        Default::default()
+    pub fn new() -> Pin<Rc<Self>> {
+        let r = Rc::pin(Rectangle {
+            geometry: Default::default(),
+            layout_info: Default::default(),
+            color: Default::default(),
+            update_connection: Default::default(),
+            _pinned: unsafe { AlwaysPinned::new() },
+        });
+        set_owner(&r);
+        r.color().set_owner(&r);
+        r
+    }
+
+    pub fn color(&self) -> Pin<&Property<'a, QColor>> {
+        unsafe { Pin::new_unchecked(&self.color) }
     }
 }
 
@@ -694,7 +752,11 @@ impl QmlItemWrapper {
     /// Link a Property to a QML property of the item.
     /// When the Property is changed, it will be updated the property on QQuickItem with the
     /// given name. (Not the other way around).
-    pub fn link_property<'a, T: QMetaType>(&self, p: &Property<'a, T>, name: &'static CStr) {
+    pub fn link_property<'a, T: QMetaType>(
+        &self,
+        p: Pin<&Property<'a, T>>,
+        name: &'static CStr,
+    ) -> Connection<'a> {
         let js = self.internal_item.borrow().clone();
         let func = move |t: &T| {
             let var = t.to_qvariant();
@@ -708,7 +770,7 @@ impl QmlItemWrapper {
             });
         };
         func(&p.value());
-        p.on_notify(func);
+        p.on_notify(func)
     }
 
     // unsafe because the node is not typed to this particular item
@@ -739,26 +801,25 @@ pub mod alignment {
     pub const TOP: i32 = 32;
     pub const BOTTOM: i32 = 64;
     pub const VCENTER: i32 = 128;
-
 }
 
 /// Wraps a QtQuick Text
-#[derive(Default)]
 pub struct Text<'a> {
-    pub geometry: Geometry<'a>,
-    pub layout_info: LayoutInfo<'a>,
-    pub text: Property<'a, QString>,
-    pub vertical_alignment: Property<'a, i32>,
-    pub horizontal_alignment: Property<'a, i32>,
+    geometry: Geometry<'a>,
+    layout_info: LayoutInfo<'a>,
+    text: Property<'a, QString>,
+    vertical_alignment: Property<'a, i32>,
+    horizontal_alignment: Property<'a, i32>,
     wrapper: QmlItemWrapper,
+    _pinned: AlwaysPinned,
 }
 
 impl<'a> Item<'a> for Text<'a> {
-    fn geometry(&self) -> &Geometry<'a> {
-        &self.geometry
+    fn geometry(&self) -> Pin<&Geometry<'a>> {
+        unsafe { Pin::new_unchecked(&self.geometry) }
     }
-    fn layout_info(&self) -> &LayoutInfo<'a> {
-        &self.layout_info
+    fn layout_info(&self) -> Pin<&LayoutInfo<'a>> {
+        unsafe { Pin::new_unchecked(&self.layout_info) }
     }
 
     fn update_paint_node(
@@ -782,53 +843,119 @@ impl<'a> Item<'a> for Text<'a> {
 
     fn init(&self, item: &(dyn QQuickItem + 'a)) {
         self.wrapper.init(item, "Text".into());
-        self.wrapper.link_property(&self.text, cstr!("text"));
+        self.wrapper.link_property(self.text(), cstr!("text"));
         self.wrapper
-            .link_property(&self.geometry.width, cstr!("width"));
+            .link_property(self.geometry().width(), cstr!("width"));
         self.wrapper
-            .link_property(&self.geometry.height, cstr!("height"));
+            .link_property(self.geometry().height(), cstr!("height"));
         self.wrapper
-            .link_property(&self.vertical_alignment, cstr!("verticalAlignment"));
+            .link_property(self.vertical_alignment(), cstr!("verticalAlignment"));
         self.wrapper
-            .link_property(&self.horizontal_alignment, cstr!("horizontalAlignment"));
+            .link_property(self.horizontal_alignment(), cstr!("horizontalAlignment"));
+    }
+
+    fn add_to_graph(&self, name: &str, graph: &mut Graph) {
+        self.geometry()
+            .add_to_graph(&format!("{}.geometry", name), graph);
+        self.layout_info()
+            .add_to_graph(&format!("{}.layout_info", name), graph);
+        self.text()
+            .add_to_graph_with(&format!("{}.text", name), graph, |t| t.to_string());
+        self.vertical_alignment()
+            .add_to_graph(&format!("{}.vertical_alignment", name), graph);
+        self.horizontal_alignment()
+            .add_to_graph(&format!("{}.horizontal_alignment", name), graph);
     }
 }
 impl<'a> Text<'a> {
-    pub fn new() -> Rc<Self> {
-        Default::default()
+    pub fn new() -> Pin<Rc<Self>> {
+        let r = Rc::pin(Text {
+            geometry: Default::default(),
+            layout_info: Default::default(),
+            text: Default::default(),
+            vertical_alignment: Default::default(),
+            horizontal_alignment: Default::default(),
+            wrapper: Default::default(),
+            _pinned: unsafe { AlwaysPinned::new() },
+        });
+        set_owner(&r);
+        r.text().set_owner(&r);
+        r.vertical_alignment().set_owner(&r);
+        r.horizontal_alignment().set_owner(&r);
+        r
+    }
+
+    pub fn text(&self) -> Pin<&Property<'a, QString>> {
+        unsafe { Pin::new_unchecked(&self.text) }
+    }
+    pub fn vertical_alignment(&self) -> Pin<&Property<'a, i32>> {
+        unsafe { Pin::new_unchecked(&self.vertical_alignment) }
+    }
+    pub fn horizontal_alignment(&self) -> Pin<&Property<'a, i32>> {
+        unsafe { Pin::new_unchecked(&self.horizontal_alignment) }
     }
 }
 
 /// Similar to a QtQuick MouseArea
-#[derive(Default)]
 pub struct MouseArea<'a> {
-    pub geometry: Geometry<'a>,
-    pub layout_info: LayoutInfo<'a>,
-    pub pressed: Property<'a, bool>,
-    pub on_clicked: Signal<'a>,
+    geometry: Geometry<'a>,
+    layout_info: LayoutInfo<'a>,
+    pressed: PinnedProperty<'a, bool>,
+    on_clicked: Signal<'a, (QPointF, MouseButton)>,
+    _pinned: AlwaysPinned,
 }
 
 impl<'a> Item<'a> for MouseArea<'a> {
-    fn geometry(&self) -> &Geometry<'a> {
-        &self.geometry
+    fn geometry(&self) -> Pin<&Geometry<'a>> {
+        unsafe { Pin::new_unchecked(&self.geometry) }
     }
-    fn layout_info(&self) -> &LayoutInfo<'a> {
-        &self.layout_info
+    fn layout_info(&self) -> Pin<&LayoutInfo<'a>> {
+        unsafe { Pin::new_unchecked(&self.layout_info) }
     }
     fn mouse_event(&self, event: MouseEvent) -> bool {
         match event {
-            MouseEvent::Press(_) => self.pressed.set(true),
-            MouseEvent::Release(_) => {
-                self.pressed.set(false);
-                self.on_clicked.emit();
+            MouseEvent::Press(..) => self.set_pressed(true),
+            MouseEvent::Release(pos, button) => {
+                self.set_pressed(false);
+                self.on_clicked().emit((pos, button));
             }
             _ => {}
         }
         true
     }
+
+    fn add_to_graph(&self, name: &str, graph: &mut Graph) {
+        self.geometry()
+            .add_to_graph(&format!("{}.geometry", name), graph);
+        self.layout_info()
+            .add_to_graph(&format!("{}.layout_info", name), graph);
+        self.pressed()
+            .add_to_graph(&format!("{}.pressed", name), graph);
+    }
 }
 impl<'a> MouseArea<'a> {
-    pub fn new() -> Rc<Self> {
-        Default::default()
+    pub fn new() -> Pin<Rc<Self>> {
+        let r = Rc::pin(MouseArea {
+            geometry: Default::default(),
+            layout_info: Default::default(),
+            pressed: unsafe { PinnedProperty::new(Default::default()) },
+            on_clicked: Default::default(),
+            _pinned: unsafe { AlwaysPinned::new() },
+        });
+        set_owner(&r);
+        r.pressed.property().set_owner(&r);
+        r
+    }
+
+    /// Whether the mouse button is down. Only the mouse events can change it.
+    pub fn pressed(&self) -> &ReadOnlyProperty<'a, bool> {
+        self.pressed.property().as_read_only()
+    }
+    fn set_pressed(&self, pressed: bool) {
+        self.pressed.property().set(pressed)
+    }
+    /// Emitted with the position of the mouse and the button when the button is released
+    pub fn on_clicked(&self) -> &Signal<'a, (QPointF, MouseButton)> {
+        &self.on_clicked
     }
 }
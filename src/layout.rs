@@ -0,0 +1,265 @@
+//! The geometry of the items and the layout engine, which do not depend on Qt.
+
+use crate::properties::{Graph, Property};
+use std::pin::Pin;
+use std::rc::Rc;
+
+/// The geometry of an item. It is stored inline in the item, so it is pinned with it.
+#[derive(Default)]
+pub struct Geometry<'a> {
+    x: Property<'a, f64>,
+    y: Property<'a, f64>,
+    width: Property<'a, f64>,
+    height: Property<'a, f64>,
+}
+impl<'a> Geometry<'a> {
+    pub fn x(self: Pin<&Self>) -> Pin<&Property<'a, f64>> {
+        unsafe { self.map_unchecked(|s| &s.x) }
+    }
+    pub fn y(self: Pin<&Self>) -> Pin<&Property<'a, f64>> {
+        unsafe { self.map_unchecked(|s| &s.y) }
+    }
+    pub fn width(self: Pin<&Self>) -> Pin<&Property<'a, f64>> {
+        unsafe { self.map_unchecked(|s| &s.width) }
+    }
+    pub fn height(self: Pin<&Self>) -> Pin<&Property<'a, f64>> {
+        unsafe { self.map_unchecked(|s| &s.height) }
+    }
+    pub fn left(self: Pin<&Self>) -> f64 {
+        self.x().get()
+    }
+    pub fn top(self: Pin<&Self>) -> f64 {
+        self.y().get()
+    }
+    pub fn right(self: Pin<&Self>) -> f64 {
+        self.x().get() + self.width().get()
+    }
+    pub fn bottom(self: Pin<&Self>) -> f64 {
+        self.y().get() + self.height().get()
+    }
+    pub fn vertical_center(self: Pin<&Self>) -> f64 {
+        self.x().get() + self.width().get() / 2.
+    }
+    pub fn horizontal_center(self: Pin<&Self>) -> f64 {
+        self.y().get() + self.height().get() / 2.
+    }
+
+    /// Register the item containing it, see `Property::set_owner`
+    pub fn set_owner<I: 'a>(self: Pin<&Self>, owner: &Pin<Rc<I>>) {
+        self.x().set_owner(owner);
+        self.y().set_owner(owner);
+        self.width().set_owner(owner);
+        self.height().set_owner(owner);
+    }
+
+    pub fn add_to_graph(self: Pin<&Self>, name: &str, graph: &mut Graph) {
+        self.x().add_to_graph(&format!("{}.x", name), graph);
+        self.y().add_to_graph(&format!("{}.y", name), graph);
+        self.width().add_to_graph(&format!("{}.width", name), graph);
+        self.height()
+            .add_to_graph(&format!("{}.height", name), graph);
+    }
+}
+
+#[cfg(feature = "qt")]
+impl<'a> Geometry<'a> {
+    pub fn to_qrectf(self: Pin<&Self>) -> qmetaobject::QRectF {
+        qmetaobject::QRectF {
+            x: self.left(),
+            y: self.top(),
+            width: self.width().get(),
+            height: self.height().get(),
+        }
+    }
+}
+/*
+enum SizePolicy {
+    Fixed(f64),
+    Minimum(f64),
+    Maximum(f64)
+}*/
+
+/// The size constraints of an item, stored inline in the item like its Geometry.
+pub struct LayoutInfo<'a> {
+    preferred_width: Property<'a, f64>,
+    preferred_height: Property<'a, f64>,
+    maximum_width: Property<'a, f64>,
+    maximum_height: Property<'a, f64>,
+    minimum_width: Property<'a, f64>,
+    minimum_height: Property<'a, f64>,
+}
+impl<'a> Default for LayoutInfo<'a> {
+    fn default() -> Self {
+        LayoutInfo {
+            preferred_width: 0.0.into(),
+            preferred_height: 0.0.into(),
+            maximum_height: std::f64::MAX.into(),
+            maximum_width: std::f64::MAX.into(),
+            minimum_width: 0.0.into(),
+            minimum_height: 0.0.into(),
+        }
+    }
+}
+impl<'a> LayoutInfo<'a> {
+    pub fn preferred_width(self: Pin<&Self>) -> Pin<&Property<'a, f64>> {
+        unsafe { self.map_unchecked(|s| &s.preferred_width) }
+    }
+    pub fn preferred_height(self: Pin<&Self>) -> Pin<&Property<'a, f64>> {
+        unsafe { self.map_unchecked(|s| &s.preferred_height) }
+    }
+    pub fn maximum_width(self: Pin<&Self>) -> Pin<&Property<'a, f64>> {
+        unsafe { self.map_unchecked(|s| &s.maximum_width) }
+    }
+    pub fn maximum_height(self: Pin<&Self>) -> Pin<&Property<'a, f64>> {
+        unsafe { self.map_unchecked(|s| &s.maximum_height) }
+    }
+    pub fn minimum_width(self: Pin<&Self>) -> Pin<&Property<'a, f64>> {
+        unsafe { self.map_unchecked(|s| &s.minimum_width) }
+    }
+    pub fn minimum_height(self: Pin<&Self>) -> Pin<&Property<'a, f64>> {
+        unsafe { self.map_unchecked(|s| &s.minimum_height) }
+    }
+
+    fn properties(self: Pin<&Self>) -> [(Pin<&Property<'a, f64>>, &'static str); 6] {
+        [
+            (self.preferred_width(), "preferred_width"),
+            (self.preferred_height(), "preferred_height"),
+            (self.maximum_width(), "maximum_width"),
+            (self.maximum_height(), "maximum_height"),
+            (self.minimum_width(), "minimum_width"),
+            (self.minimum_height(), "minimum_height"),
+        ]
+    }
+
+    /// Register the item containing it, see `Property::set_owner`
+    pub fn set_owner<I: 'a>(self: Pin<&Self>, owner: &Pin<Rc<I>>) {
+        for (p, _) in self.properties().iter() {
+            p.set_owner(owner);
+        }
+    }
+
+    pub fn add_to_graph(self: Pin<&Self>, name: &str, graph: &mut Graph) {
+        for (p, field) in self.properties().iter() {
+            p.add_to_graph(&format!("{}.{}", name, field), graph);
+        }
+    }
+}
+
+pub mod layout_engine {
+
+    use std::ops::Add;
+
+    pub type Coord = f64;
+
+    #[derive(Default)]
+    pub struct ItemInfo {
+        pub min: Coord,
+        pub max: Coord,
+        pub preferred: Coord,
+        pub expand: usize,
+    }
+
+    impl<'a> Add<&'a ItemInfo> for ItemInfo {
+        type Output = ItemInfo;
+
+        fn add(self, other: &'a ItemInfo) -> ItemInfo {
+            ItemInfo {
+                min: self.min + other.min,
+                max: self.max + other.max, // the idea is that it saturate with the max value or infinity
+                preferred: self.preferred + other.preferred,
+                expand: self.expand + other.expand,
+            }
+        }
+    }
+
+    pub fn compute_total_info(info: &[ItemInfo], spacing: Coord) -> ItemInfo {
+        let mut sum: ItemInfo = info.iter().fold(ItemInfo::default(), Add::add);
+        let total_spacing = spacing * info.len().saturating_sub(1) as Coord;
+        sum.min += total_spacing;
+        sum.max += total_spacing;
+        sum.preferred += total_spacing;
+        sum
+    }
+
+    #[derive(Clone, Copy)]
+    pub struct ItemResult {
+        pub size: Coord,
+        pub pos: Coord,
+    }
+
+    pub fn do_layout(
+        info: &[ItemInfo],
+        total: ItemInfo,
+        spacing: Coord,
+        size: Coord,
+    ) -> Vec<ItemResult> {
+        // FIXME! consider maximum, or the case where we are smaller that the minimum
+        if size < total.preferred {
+            let to_remove = total.preferred - size;
+            let total_allowed_to_remove = total.preferred - total.min;
+
+            let mut pos = 0 as Coord;
+            info.iter()
+                .map(|it| {
+                    let s = it.preferred
+                        - (it.preferred - it.min) * to_remove / total_allowed_to_remove;
+                    let p = pos;
+                    pos += s + spacing;
+                    ItemResult { size: s, pos: p }
+                })
+                .collect()
+        } else {
+            let to_add = size - total.preferred;
+            //let total_allowed_to_add = total.max - preferred;
+
+            let mut pos = 0 as Coord;
+            info.iter()
+                .map(|it| {
+                    let s = if total.expand > 0 {
+                        it.preferred + to_add * it.expand as Coord / total.expand as Coord
+                    } else {
+                        it.preferred + to_add / info.len() as Coord
+                    };
+                    let p = pos;
+                    pos += s + spacing;
+                    ItemResult { size: s, pos: p }
+                })
+                .collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::layout_engine::*;
+
+    #[test]
+    fn test_layout() {
+        let info = vec![
+            ItemInfo {
+                min: 10.,
+                max: 100.,
+                preferred: 20.,
+                expand: 0,
+            },
+            ItemInfo {
+                min: 10.,
+                max: 100.,
+                preferred: 40.,
+                expand: 1,
+            },
+        ];
+        let total = compute_total_info(&info, 5.);
+        assert_eq!(total.preferred, 65.);
+        let r = do_layout(&info, total, 5., 85.);
+        assert_eq!((r[0].pos, r[0].size), (0., 20.));
+        assert_eq!((r[1].pos, r[1].size), (25., 60.));
+    }
+
+    #[test]
+    fn test_empty_layout() {
+        let total = compute_total_info(&[], 5.);
+        assert_eq!((total.min, total.preferred), (0., 0.));
+        assert!(do_layout(&[], total, 5., 85.).is_empty());
+    }
+}
@@ -6,26 +6,66 @@
 //! graphs.
 //! (For some node such as text node, there is an hidden QQuickItem
 //! because there is no public API to get a text node)
-//! only the `items` module depends on Qt.
+//! only the `items` and `quick` modules depend on Qt.
+//!
+//! Features:
+//!  - `qt` (default): the `items` and `quick` modules, which need Qt.
+//!  - `std` (default): everything else but `properties_impl`. Without it, the crate is `no_std`
+//!    and only needs `alloc`.
+//!  - `derive`: `#[derive(Properties)]` to use the `properties_impl` properties from structs.
+//!  - `stream`: `Property::changes` and `Signal::stream`, to use the properties from async code.
+//!  - `tracing`: report the evaluation of the bindings and the changes of the properties to
+//!    the `tracing` ecosystem, to find the bindings which are slow or evaluated too often.
 
+#![cfg_attr(not(feature = "std"), no_std)]
 #![recursion_limit = "512"]
 
+extern crate alloc;
+
+// So that the code generated by the derive macro also works within this crate
+extern crate self as propertybindings;
+
+#[cfg(feature = "qt")]
 #[macro_use]
 extern crate cstr;
 
+#[cfg(feature = "qt")]
 #[macro_use]
 extern crate qmetaobject;
 
+#[cfg(feature = "qt")]
 #[macro_use]
 extern crate cpp;
 
+#[cfg(feature = "std")]
 #[macro_use]
 pub mod properties;
+#[cfg(feature = "std")]
 pub use crate::properties::*;
+#[cfg(feature = "std")]
 pub mod anchors;
+#[cfg(feature = "std")]
+pub mod animation;
+#[cfg(feature = "std")]
+pub mod layout;
+#[cfg(feature = "std")]
 #[macro_use]
 pub mod rslm;
+#[cfg(feature = "qt")]
 pub mod items;
+#[cfg(feature = "qt")]
 pub mod quick;
 
 pub mod properties_impl;
+
+#[cfg(feature = "std")]
+pub mod collections;
+#[cfg(feature = "std")]
+pub mod combinators;
+#[cfg(feature = "std")]
+pub mod cross_thread;
+#[cfg(feature = "std")]
+pub mod executor;
+
+#[cfg(feature = "stream")]
+pub mod stream;
@@ -1,20 +1,33 @@
-//! This module is the old implementation of the property system, before Pin existed.
-//! The module properties_impl contains the new implementation and is used as implementation for this
+//! The properties, signals and bindings used by the items and by `rsml!`.
+//! The module properties_impl contains the implementation: the properties are stored inline in the
+//! items, which are pinned, and only allocate for their binding, callbacks or weak references.
+//! `RcProperty` keeps the API of the previous versions, where each property was in its own Rc.
 use crate::properties_impl;
 use std;
-use std::cell::RefCell;
+use std::cell::{Cell, OnceCell, RefCell};
 use std::convert::From;
 use std::default::Default;
-use std::marker::PhantomData;
+use std::marker::PhantomPinned;
 use std::pin::Pin;
 use std::rc::{Rc, Weak};
 
+pub use crate::properties_impl::graph::{Graph, GraphNode};
+pub use crate::properties_impl::{
+    batch, set_binding_loop_policy, BindingLoopError, BindingLoopPolicy, ValueRef,
+};
+pub use pin_weak::rc::PinWeak;
+
 /// A binding is a function that returns a value of type T
 pub trait PropertyBindingFn<T> {
     fn run(&self) -> Option<T>;
     fn description(&self) -> String {
         String::default()
     }
+    /// Same as run, with the reason of the failure. See BindingErrorPolicy.
+    fn try_run(&self) -> Result<T, String> {
+        self.run()
+            .ok_or_else(|| "the binding returned None".to_owned())
+    }
 }
 impl<F, T> PropertyBindingFn<T> for F
 where
@@ -48,105 +61,899 @@ where
         (self.0).clone()
     }
 }
+/// Use a function returning a Result as a binding, the error is reported as a BindingError.
+pub struct Fallible<F>(pub F);
+impl<F, T, E> PropertyBindingFn<T> for Fallible<F>
+where
+    F: Fn() -> Result<T, E>,
+    E: std::fmt::Display,
+{
+    fn run(&self) -> Option<T> {
+        (self.0)().ok()
+    }
+    fn try_run(&self) -> Result<T, String> {
+        (self.0)().map_err(|e| e.to_string())
+    }
+}
+
+/// A binding which failed to produce a value
+#[derive(Debug, Clone)]
+pub struct BindingError {
+    pub description: String,
+    pub message: String,
+}
+
+impl std::fmt::Display for BindingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.description.is_empty() {
+            write!(f, "Binding failed: {}", self.message)
+        } else {
+            write!(f, "Binding {} failed: {}", self.description, self.message)
+        }
+    }
+}
+
+impl std::error::Error for BindingError {}
+
+/// What to do when a binding fails to produce a value.
+/// The signal handlers which fail are skipped, their return value is not accumulated.
+#[derive(Clone, Default)]
+pub enum BindingErrorPolicy {
+    /// The property keeps its previous value (the default)
+    #[default]
+    KeepValue,
+    /// The property is set to the default value of its type. Same as KeepValue for a signal.
+    UseDefault,
+    /// Print the error on stderr, and keep the previous value
+    Log,
+    Panic,
+    /// Call the function, and keep the previous value
+    Hook(Rc<dyn Fn(&BindingError)>),
+}
+
+thread_local!(static BINDING_ERROR_POLICY: RefCell<BindingErrorPolicy> = RefCell::default());
+
+/// Set the BindingErrorPolicy for the current thread
+pub fn set_binding_error_policy(policy: BindingErrorPolicy) {
+    BINDING_ERROR_POLICY.with(|p| *p.borrow_mut() = policy);
+}
 
-#[derive(Default, Clone)]
+/// Apply the BindingErrorPolicy. Returns true if the default value should be used.
+fn report_binding_error(error: BindingError) -> bool {
+    match BINDING_ERROR_POLICY.with(|p| p.borrow().clone()) {
+        BindingErrorPolicy::KeepValue => false,
+        BindingErrorPolicy::UseDefault => true,
+        BindingErrorPolicy::Log => {
+            eprintln!("{}", error);
+            false
+        }
+        BindingErrorPolicy::Panic => panic!("{}", error),
+        BindingErrorPolicy::Hook(f) => {
+            f(&error);
+            false
+        }
+    }
+}
+
+/// The callbacks of a Property or a Signal, shared with their Connection
+struct Slots<C> {
+    slots: RefCell<Vec<(usize, C)>>,
+    next_id: Cell<usize>,
+    /// The callback being called, see `run`
+    running: Cell<Option<usize>>,
+    /// The running callback, if it was disconnected from itself. It is dropped once it returned.
+    removed: RefCell<Option<C>>,
+}
+impl<C> Default for Slots<C> {
+    fn default() -> Self {
+        Slots {
+            slots: Default::default(),
+            next_id: Default::default(),
+            running: Default::default(),
+            removed: Default::default(),
+        }
+    }
+}
+impl<C> Slots<C> {
+    fn add(&self, c: C) -> usize {
+        let id = self.reserve_id();
+        self.insert(id, c);
+        id
+    }
+
+    /// The id of the next callback, which is then inserted with `insert`
+    fn reserve_id(&self) -> usize {
+        let id = self.next_id.get();
+        self.next_id.set(id + 1);
+        id
+    }
+
+    fn insert(&self, id: usize, c: C) {
+        self.drop_removed();
+        self.slots.borrow_mut().push((id, c));
+    }
+
+    fn drop_removed(&self) {
+        if self.running.get().is_none() {
+            let removed = self.removed.borrow_mut().take();
+            core::mem::drop(removed);
+        }
+    }
+
+    /// Call `f` on behalf of the callback with this id, if it is still connected.
+    /// The callbacks which do not own a clone of themselves while they run, such as the
+    /// ChangeEvent, use it so that disconnecting them from `f` does not drop them under its feet.
+    fn run(this: &Weak<Self>, id: usize, f: impl FnOnce()) {
+        match this.upgrade() {
+            Some(s) if s.is_connected(id) => {
+                s.drop_removed();
+                s.running.set(Some(id));
+            }
+            _ => return,
+        }
+        f();
+        // The slots are gone if the property was dropped from `f`
+        if let Some(s) = this.upgrade() {
+            s.running.set(None);
+        }
+    }
+}
+
+trait Disconnect {
+    fn disconnect(&self, id: usize);
+    fn is_connected(&self, id: usize) -> bool;
+}
+impl<C> Disconnect for Slots<C> {
+    fn disconnect(&self, id: usize) {
+        let removed = {
+            let mut v = self.slots.borrow_mut();
+            v.iter().position(|x| x.0 == id).map(|i| v.remove(i))
+        };
+        match removed {
+            // Still running: dropped after it returns, see `run`
+            Some((_, c)) if self.running.get() == Some(id) => {
+                *self.removed.borrow_mut() = Some(c);
+            }
+            // dropped outside of the borrow, in case it owns something with callbacks
+            removed => core::mem::drop(removed),
+        }
+    }
+    fn is_connected(&self, id: usize) -> bool {
+        self.slots.borrow().iter().any(|x| x.0 == id)
+    }
+}
+
+/// A handle to a callback registered with Property::on_notify or Signal::set_binding.
+/// The callback stays connected when the handle is dropped, use `scoped` for that.
+#[derive(Clone)]
+pub struct Connection<'a> {
+    slots: Weak<dyn Disconnect + 'a>,
+    id: usize,
+}
+impl<'a> Connection<'a> {
+    fn new<C: 'a>(slots: &Rc<Slots<C>>, id: usize) -> Self {
+        let slots: Rc<dyn Disconnect + 'a> = slots.clone();
+        Connection {
+            slots: Rc::downgrade(&slots),
+            id,
+        }
+    }
+
+    /// Remove the callback. Does nothing if it was already disconnected.
+    pub fn disconnect(&self) {
+        if let Some(s) = self.slots.upgrade() {
+            s.disconnect(self.id)
+        }
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.slots
+            .upgrade()
+            .is_some_and(|s| s.is_connected(self.id))
+    }
+
+    /// Turn into a guard which disconnects the callback when it is dropped.
+    pub fn scoped(self) -> ScopedConnection<'a> {
+        ScopedConnection(Some(self))
+    }
+}
+
+/// Disconnects the callback when dropped.
+#[must_use]
+pub struct ScopedConnection<'a>(Option<Connection<'a>>);
+impl<'a> ScopedConnection<'a> {
+    /// Keep the callback connected
+    pub fn release(mut self) -> Connection<'a> {
+        // Only None after release, which consumes self
+        self.0.take().unwrap()
+    }
+}
+impl<'a> Drop for ScopedConnection<'a> {
+    fn drop(&mut self) {
+        if let Some(c) = &self.0 {
+            c.disconnect();
+        }
+    }
+}
+
+/// Adapts a PropertyBindingFn to the Binding trait of properties_impl
+struct BindingFnWrapper<F>(F);
+impl<T: Default, F: PropertyBindingFn<T>> properties_impl::Binding<T> for BindingFnWrapper<F> {
+    fn call(self: Pin<&Self>) -> T {
+        self.try_call().unwrap_or_default()
+    }
+    fn try_call(self: Pin<&Self>) -> Option<T> {
+        let message = match self.0.try_run() {
+            Ok(t) => return Some(t),
+            Err(message) => message,
+        };
+        let error = BindingError {
+            description: self.0.description(),
+            message,
+        };
+        if report_binding_error(error) {
+            Some(T::default())
+        } else {
+            None
+        }
+    }
+    fn description(&self) -> String {
+        self.0.description()
+    }
+}
+
+/// Invalidates the WeakProperty when the property is dropped, and knows the allocation which
+/// contains the property, see `Property::set_owner`.
+#[derive(Default)]
+struct AliveToken<'a> {
+    token: OnceCell<Rc<()>>,
+    owner: OnceCell<Weak<dyn Keep + 'a>>,
+}
+
+/// A weak reference to a Property, to be captured by bindings and callbacks.
+/// It does nothing once the property was dropped.
+///
+/// While a binding or a callback is called through it, it keeps the owner of the property
+/// alive, so dropping the item from there is deferred until the access returns.
+/// The property must have an owner (see `Property::set_owner`): the access fails otherwise,
+/// as nothing could keep the property alive.
 pub struct WeakProperty<'a, T> {
-    d: Weak<properties_impl::Property<T>>,
-    phantom: PhantomData<&'a ()>,
+    alive: Weak<()>,
+    property: *const Property<'a, T>,
+}
+impl<'a, T> Default for WeakProperty<'a, T> {
+    fn default() -> Self {
+        WeakProperty {
+            alive: Weak::new(),
+            property: std::ptr::null(),
+        }
+    }
+}
+impl<'a, T> Clone for WeakProperty<'a, T> {
+    fn clone(&self) -> Self {
+        WeakProperty {
+            alive: self.alive.clone(),
+            property: self.property,
+        }
+    }
+}
+impl<'a, T> WeakProperty<'a, T> {
+    /// Call the function with the property, if it still exists and has an owner
+    pub fn with_property<R>(&self, f: impl FnOnce(Pin<&Property<'a, T>>) -> R) -> Option<R> {
+        // The token is only held while the owner is looked up, the owner keeps the property
+        // alive afterwards. The access fails once the owner is being dropped.
+        let _owner = {
+            let _alive = self.alive.upgrade()?;
+            unsafe { &*self.property }.alive.owner.get()?.upgrade()?
+        };
+        // The property was pinned, so it stays at this address until it is dropped, and
+        // it cannot be dropped while `_owner` is held.
+        Some(f(unsafe { Pin::new_unchecked(&*self.property) }))
+    }
+
+    /// Set the value if the property still exists.
+    /// Unlike Property::set, it does not go through the Behavior, which uses it to animate.
+    pub fn set(&self, t: T) {
+        self.with_property(|p| p.d().set(t));
+    }
 }
-impl<'a, T: Default + Clone> WeakProperty<'a, T> {
+impl<'a, T: Clone> WeakProperty<'a, T> {
     pub fn get(&self) -> Option<T> {
-        // Safe because the original RC is pinned
-        self.d
-            .upgrade()
-            .map(|x| unsafe { Pin::new_unchecked(x) }.as_ref().get())
+        self.with_property(|p| p.get())
     }
 }
 
+type ChangeCallbacks<'a> = Rc<Slots<Pin<Box<properties_impl::ChangeEvent<dyn Fn() + 'a>>>>>;
+/// Called by `set` instead of setting the value, see animation::Behavior
+pub(crate) type SetHookFn<'a, T> = Rc<dyn Fn(T) + 'a>;
+
 /// A Property represents a value which records when it is accessed. If the property's binding
-/// depends on others property, the property binding is automatically re-evaluated.
-// Fixme! the property should maybe be computed lazily, or the graph studied to avoid unnecesseray re-computation.
-pub struct Property<'a, T: Default> {
-    d: Pin<Rc<properties_impl::Property<T>>>,
-    callbacks: RefCell<Vec<Pin<Box<properties_impl::ChangeEvent<dyn Fn() + 'a>>>>>,
+/// depends on others property, the property binding is marked dirty when they change and
+/// re-evaluated the next time the property is accessed.
+///
+/// The property is stored inline, and it must be pinned to be used: the items are pinned in
+/// an Rc and have accessors returning `Pin<&Property>`, see `AlwaysPinned`.
+/// `RcProperty` is a property on its own, with the `&self` API of the previous versions.
+pub struct Property<'a, T> {
+    // The token is dropped first, and the callbacks before the property they listen to.
+    alive: AliveToken<'a>,
+    /// Allocated when the first callback is connected
+    callbacks: OnceCell<ChangeCallbacks<'a>>,
+    set_hook: RefCell<Option<SetHookFn<'a, T>>>,
+    d: properties_impl::Property<T>,
 }
 impl<'a, T: Default> Default for Property<'a, T> {
     fn default() -> Self {
+        Property::from(T::default())
+    }
+}
+impl<'a, T> From<T> for Property<'a, T> {
+    fn from(t: T) -> Self {
         Property {
-            d: Rc::pin(properties_impl::Property::default()),
+            alive: Default::default(),
             callbacks: Default::default(),
+            set_hook: Default::default(),
+            d: properties_impl::Property::new(t),
         }
     }
 }
-impl<'a, T: Default + Clone> Property<'a, T> {
-    pub fn from_binding<F: PropertyBindingFn<T> + 'a>(f: F) -> Property<'a, T> {
-        let d = Rc::pin(properties_impl::Property::default());
-        d.as_ref().set_binding_owned(move || f.run().unwrap());
-        Property {
-            d,
-            callbacks: Default::default(),
+impl<'a, T> Property<'a, T> {
+    pub(crate) fn d(self: Pin<&Self>) -> Pin<&properties_impl::Property<T>> {
+        unsafe { self.map_unchecked(|s| &s.d) }
+    }
+
+    pub(crate) fn set_hook(&self) -> &RefCell<Option<SetHookFn<'a, T>>> {
+        &self.set_hook
+    }
+
+    /// A weak reference to this property. It only gives access to the property once it has an
+    /// owner, see `set_owner`.
+    pub fn as_weak(self: Pin<&Self>) -> WeakProperty<'a, T> {
+        WeakProperty {
+            alive: Rc::downgrade(self.alive.token.get_or_init(Default::default)),
+            property: &*self,
         }
     }
 
+    /// Register the Rc which contains this property. The WeakProperty keep it alive while they
+    /// access the property, so a binding or a callback can drop the item safely.
+    /// RcProperty, the items, and the structs declared with rsml! register their properties;
+    /// it is needed for any other property accessed through a WeakProperty, which does
+    /// nothing otherwise. Only the first owner is kept.
+    pub fn set_owner<I: 'a>(self: Pin<&Self>, owner: &Pin<Rc<I>>) {
+        // Only used to keep the owner alive, it is never unpinned
+        let owner: Rc<dyn Keep + 'a> = unsafe { Pin::into_inner_unchecked(owner.clone()) };
+        let _ = self.alive.owner.set(Rc::downgrade(&owner));
+    }
+
+    /// A view of this property which cannot be set, to expose it from a component
+    pub fn as_read_only(self: Pin<&Self>) -> &ReadOnlyProperty<'a, T> {
+        // ReadOnlyProperty is repr(transparent)
+        unsafe { &*(self.get_ref() as *const Self as *const ReadOnlyProperty<'a, T>) }
+    }
+
     /// Set the value, and notify all the dependent property so their binding can be re-evaluated
-    pub fn set(&self, t: T) {
-        self.d.as_ref().set(t);
+    /// (If a Behavior is installed on this property, it animates to the value instead.)
+    pub fn set(self: Pin<&Self>, t: T) {
+        let hook = self.set_hook.borrow().clone();
+        match hook {
+            Some(hook) => hook(t),
+            None => self.d().set(t),
+        }
     }
-    pub fn set_binding<F: PropertyBindingFn<T> + 'a>(&self, f: F) {
-        self.d.as_ref().set_binding_owned(move || f.run().unwrap());
+
+    /// Set the value, but do not notify anything if it is equal to the current value.
+    pub fn set_if_changed(self: Pin<&Self>, t: T)
+    where
+        T: PartialEq,
+    {
+        self.d().set_if_changed(t);
     }
 
-    /*
-    pub fn borrow<'b>(&'b self) -> Ref<'b, T> {
-        self.d.accessed();
-        let d = self.d.borrow();
-        Ref::map(d, |d| &d.value)
-    }*/
+    /// Remove the binding. The property keeps the last value of the binding, which is not
+    /// re-evaluated, even if it depends on properties which changed.
+    pub fn remove_binding(self: Pin<&Self>) {
+        self.d().remove_binding();
+    }
 
+    /// Call `f` with a reference to the value, without cloning it.
+    /// Like `get`, this marks the property as a dependency of the binding being evaluated.
+    /// Setting the property from `f` panics.
+    pub fn with<R>(self: Pin<&Self>, f: impl FnOnce(&T) -> R) -> R {
+        self.d().with(f)
+    }
+
+    /// Borrow the value, without cloning it. Same as `with`: the property must not be set
+    /// while the ValueRef exists.
+    pub fn borrow(self: Pin<&Self>) -> ValueRef<'_, T> {
+        self.d().borrow()
+    }
+
+    /// Add this property, and the ones connected to it, to the graph.
+    pub fn add_to_graph(self: Pin<&Self>, name: &str, graph: &mut Graph)
+    where
+        T: std::fmt::Debug,
+    {
+        graph.add(name, self.d());
+    }
+
+    /// Same as add_to_graph, with a custom formatting of the value.
+    pub fn add_to_graph_with<F: Fn(&T) -> String>(
+        self: Pin<&Self>,
+        name: &str,
+        graph: &mut Graph,
+        f: F,
+    ) {
+        graph.add_with(name, self.d(), f);
+    }
+
+    /// Same as add_to_graph, for the types which do not implement Debug.
+    pub fn add_to_graph_opaque(self: Pin<&Self>, name: &str, graph: &mut Graph) {
+        graph.add_opaque(name, self.d());
+    }
+
+    /// One can add callback which are being called when the property changes.
+    /// The callback receives a copy of the value, so it may set this property.
+    pub fn on_notify<F>(self: Pin<&Self>, callback: F) -> Connection<'a>
+    where
+        F: Fn(&T) + 'a,
+        T: Clone + 'a,
+    {
+        self.on_notify_with(T::clone, move |t| callback(&t))
+    }
+
+    /// Same as on_notify, for the types which are not Clone: `snapshot` takes what the callback
+    /// needs from the borrowed value, and the callback is called once the value is released.
+    pub fn on_notify_with<U, S, F>(self: Pin<&Self>, snapshot: S, callback: F) -> Connection<'a>
+    where
+        S: Fn(&T) -> U + 'a,
+        F: Fn(U) + 'a,
+        T: 'a,
+    {
+        let callbacks = self.callbacks.get_or_init(Default::default);
+        let id = callbacks.reserve_id();
+        let slots = Rc::downgrade(callbacks);
+        // The ChangeEvent is owned by the property, and dropped before it
+        let d: *const properties_impl::Property<T> = &self.d;
+        let e = Box::pin(properties_impl::ChangeEvent::new(move || {
+            Slots::run(&slots, id, || {
+                callback(unsafe { Pin::new_unchecked(&*d) }.with(&snapshot))
+            })
+        }));
+        e.as_ref().listen(self.d());
+        callbacks.insert(id, e);
+        Connection::new(callbacks, id)
+    }
+
+    /// Keep this property and `other` in sync: `other` takes the value of this property, then
+    /// when one of them changes, the other one is set to the same value.
+    ///
+    /// The values are only set if they are different, which is what stops the propagation.
+    /// So a property keeps its binding as long as the other one only follows it.
+    /// Returns the connections of the callbacks on this property and on `other`.
+    pub fn bind_bidirectional(
+        self: Pin<&Self>,
+        other: Pin<&Property<'a, T>>,
+    ) -> (Connection<'a>, Connection<'a>)
+    where
+        T: Clone + PartialEq + 'a,
+    {
+        self.bind_bidirectional_with(other, T::clone, T::clone)
+    }
+
+    /// Same as bind_bidirectional, with a conversion function in each direction.
+    /// The conversions should round-trip: `backward(&forward(&t)) == t`, otherwise the
+    /// properties keep updating each other until they reach a value that does.
+    pub fn bind_bidirectional_with<U, F, B>(
+        self: Pin<&Self>,
+        other: Pin<&Property<'a, U>>,
+        forward: F,
+        backward: B,
+    ) -> (Connection<'a>, Connection<'a>)
+    where
+        T: PartialEq + 'a,
+        U: PartialEq + 'a,
+        F: Fn(&T) -> U + 'a,
+        B: Fn(&U) -> T + 'a,
+    {
+        // Unlike set_if_changed, this does not remove the binding if the value is the same
+        fn set_if_different<'a, T: PartialEq>(p: &WeakProperty<'a, T>, t: T) {
+            p.with_property(|p| {
+                if p.with(|v| *v != t) {
+                    p.d().set(t);
+                }
+            });
+        }
+        // The callbacks only keep weak references, the properties may be dropped in any order
+        let weak_other = other.as_weak();
+        set_if_different(&weak_other, self.with(&forward));
+        let c1 = self.on_notify_with(forward, move |u| set_if_different(&weak_other, u));
+        let weak_self = self.as_weak();
+        let c2 = other.on_notify_with(backward, move |t| set_if_different(&weak_self, t));
+        (c1, c2)
+    }
+}
+impl<'a, T: Default> Property<'a, T> {
+    pub fn set_binding<F: PropertyBindingFn<T> + 'a>(self: Pin<&Self>, f: F) {
+        self.d().set_binding_owned(BindingFnWrapper(f));
+    }
+
+    /// Same as set_binding, but when the binding is re-evaluated to a value equal to the previous
+    /// one, the callbacks and the properties that depend on this one are not notified.
+    pub fn set_binding_if_changed<F: PropertyBindingFn<T> + 'a>(self: Pin<&Self>, f: F)
+    where
+        T: PartialEq,
+    {
+        self.d()
+            .set_binding_owned(properties_impl::IfChanged(BindingFnWrapper(f)));
+    }
+}
+impl<'a, T: Clone> Property<'a, T> {
     // FIXME! remove
-    pub fn value(&self) -> T {
+    pub fn value(self: Pin<&Self>) -> T {
         self.get()
     }
 
     /// Get the value.
     /// Accessing this property from another's property binding will mark the other property as a dependency.
+    pub fn get(self: Pin<&Self>) -> T {
+        self.d().get()
+    }
+}
+
+/// A Property which can be read, and used in bindings, but not set nor bound. A component keeps
+/// the Property private, and its accessor returns `as_read_only()`. In `rsml!`, the fields
+/// declared with `@readonly` are only set by their binding in the declaration.
+///
+/// It can only be obtained from a pinned Property, so `&self` implies that it is pinned.
+#[repr(transparent)]
+pub struct ReadOnlyProperty<'a, T>(Property<'a, T>);
+impl<'a, T> ReadOnlyProperty<'a, T> {
+    pub(crate) fn property(&self) -> Pin<&Property<'a, T>> {
+        unsafe { Pin::new_unchecked(&self.0) }
+    }
+
+    pub fn with<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        self.property().with(f)
+    }
+    pub fn borrow(&self) -> ValueRef<'_, T> {
+        self.property().borrow()
+    }
+    pub fn on_notify<F>(&self, callback: F) -> Connection<'a>
+    where
+        F: Fn(&T) + 'a,
+        T: Clone + 'a,
+    {
+        self.property().on_notify(callback)
+    }
+    pub fn on_notify_with<U, S, F>(&self, snapshot: S, callback: F) -> Connection<'a>
+    where
+        S: Fn(&T) -> U + 'a,
+        F: Fn(U) + 'a,
+        T: 'a,
+    {
+        self.property().on_notify_with(snapshot, callback)
+    }
+    pub fn add_to_graph(&self, name: &str, graph: &mut Graph)
+    where
+        T: std::fmt::Debug,
+    {
+        self.property().add_to_graph(name, graph)
+    }
+    pub fn add_to_graph_with<F: Fn(&T) -> String>(&self, name: &str, graph: &mut Graph, f: F) {
+        self.property().add_to_graph_with(name, graph, f)
+    }
+    pub fn add_to_graph_opaque(&self, name: &str, graph: &mut Graph) {
+        self.property().add_to_graph_opaque(name, graph)
+    }
+}
+impl<'a, T: Clone> ReadOnlyProperty<'a, T> {
     pub fn get(&self) -> T {
-        self.d.as_ref().get()
+        self.property().get()
     }
+}
 
-    pub fn as_weak(&self) -> WeakProperty<'a, T> {
-        WeakProperty {
-            d: Rc::downgrade(unsafe {
-                // FIXME: use Pin::into_inner_unchecked
-                std::mem::transmute::<
-                    &std::pin::Pin<std::rc::Rc<properties_impl::Property<T>>>,
-                    &std::rc::Rc<properties_impl::Property<T>>,
-                >(&self.d)
-            }),
-            phantom: PhantomData,
+/// A field of the structs which are always pinned, such as the items and the structs declared
+/// with `rsml!`. It can only be created with unsafe code, which promises that the struct is
+/// pinned before it is used, usually with `Rc::pin`, so that `&self` implies that the struct is
+/// pinned. The accessors can then return `Pin<&Property>` from `&self`.
+pub struct AlwaysPinned(PhantomPinned);
+impl AlwaysPinned {
+    /// # Safety
+    /// The struct containing it must be pinned before its fields are accessed.
+    pub unsafe fn new() -> Self {
+        AlwaysPinned(PhantomPinned)
+    }
+}
+
+/// A property field of a struct which is always pinned, see `AlwaysPinned`, which can be
+/// accessed pinned without unsafe code. rsml! uses it for the `@readonly` properties: their
+/// accessor only returns a ReadOnlyProperty, and the code of the module sets them through the
+/// private field.
+pub struct PinnedProperty<'a, T>(Property<'a, T>);
+impl<'a, T> PinnedProperty<'a, T> {
+    /// # Safety
+    /// The struct containing it must be pinned before its fields are accessed.
+    pub unsafe fn new(property: Property<'a, T>) -> Self {
+        PinnedProperty(property)
+    }
+
+    /// The pinned property
+    pub fn property(&self) -> Pin<&Property<'a, T>> {
+        unsafe { Pin::new_unchecked(&self.0) }
+    }
+}
+
+/// Keeps an item alive, whatever its type
+pub(crate) trait Keep {}
+impl<T: ?Sized> Keep for T {}
+
+/// A strong reference to a property of an item pinned in an Rc, which keeps the item alive.
+/// rsml! uses it for the aliases.
+pub struct PropertyRef<'a, T> {
+    _item: Pin<Rc<dyn Keep + 'a>>,
+    property: *const Property<'a, T>,
+}
+impl<'a, T> PropertyRef<'a, T> {
+    /// `project` returns the property from the item.
+    pub fn new<I: 'a, F>(item: Pin<Rc<I>>, project: F) -> Self
+    where
+        F: for<'r> FnOnce(Pin<&'r I>) -> Pin<&'r Property<'a, T>>,
+    {
+        let property: *const Property<'a, T> = &*project(item.as_ref());
+        PropertyRef {
+            _item: item,
+            property,
         }
     }
 
-    /// One can add callback which are being called when the property changes.
-    pub fn on_notify<F>(&self, callback: F)
+    /// Same as new, but refers to a property of its own if the item is gone.
+    pub fn new_or_detached<I: 'a, F>(item: Option<Pin<Rc<I>>>, project: F) -> Self
+    where
+        F: for<'r> FnOnce(Pin<&'r I>) -> Pin<&'r Property<'a, T>>,
+        T: Default + 'a,
+    {
+        match item {
+            Some(item) => Self::new(item, project),
+            None => Self::new(RcProperty::default().0, |p| p),
+        }
+    }
+
+    pub fn get(&self) -> Pin<&Property<'a, T>> {
+        // The item is pinned and kept alive by the Rc
+        unsafe { Pin::new_unchecked(&*self.property) }
+    }
+}
+
+/// A property on its own, in an Rc, with the `&self` API of the Property of the previous
+/// versions, to ease the migration. Also handy for the properties which are not part of an
+/// item, as the model of an application.
+pub struct RcProperty<'a, T>(Pin<Rc<Property<'a, T>>>);
+impl<'a, T: Default + 'a> Default for RcProperty<'a, T> {
+    fn default() -> Self {
+        RcProperty::new(Property::default())
+    }
+}
+impl<'a, T: 'a> From<T> for RcProperty<'a, T> {
+    fn from(t: T) -> Self {
+        RcProperty::new(Property::from(t))
+    }
+}
+impl<'a, T> RcProperty<'a, T> {
+    fn new(property: Property<'a, T>) -> Self
+    where
+        T: 'a,
+    {
+        let rc = Rc::pin(property);
+        rc.as_ref().set_owner(&rc);
+        RcProperty(rc)
+    }
+
+    /// The pinned property
+    pub fn property(&self) -> Pin<&Property<'a, T>> {
+        self.0.as_ref()
+    }
+
+    pub fn set(&self, t: T) {
+        self.property().set(t)
+    }
+    pub fn set_if_changed(&self, t: T)
+    where
+        T: PartialEq,
+    {
+        self.property().set_if_changed(t)
+    }
+    pub fn with<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        self.property().with(f)
+    }
+    pub fn borrow(&self) -> ValueRef<'_, T> {
+        self.property().borrow()
+    }
+    pub fn add_to_graph(&self, name: &str, graph: &mut Graph)
+    where
+        T: std::fmt::Debug,
+    {
+        self.property().add_to_graph(name, graph)
+    }
+    pub fn add_to_graph_with<F: Fn(&T) -> String>(&self, name: &str, graph: &mut Graph, f: F) {
+        self.property().add_to_graph_with(name, graph, f)
+    }
+    pub fn add_to_graph_opaque(&self, name: &str, graph: &mut Graph) {
+        self.property().add_to_graph_opaque(name, graph)
+    }
+
+    /// Return an alias: another handle to this same property, like a QML `property alias`.
+    pub fn alias(&self) -> RcProperty<'a, T> {
+        RcProperty(self.0.clone())
+    }
+
+    pub fn as_weak(&self) -> WeakProperty<'a, T> {
+        self.property().as_weak()
+    }
+    pub fn as_read_only(&self) -> &ReadOnlyProperty<'a, T> {
+        self.property().as_read_only()
+    }
+    pub fn on_notify<F>(&self, callback: F) -> Connection<'a>
     where
         F: Fn(&T) + 'a,
+        T: Clone + 'a,
+    {
+        self.property().on_notify(callback)
+    }
+    pub fn on_notify_with<U, S, F>(&self, snapshot: S, callback: F) -> Connection<'a>
+    where
+        S: Fn(&T) -> U + 'a,
+        F: Fn(U) + 'a,
         T: 'a,
     {
-        let d = self.d.clone();
-        let e = Box::pin(properties_impl::ChangeEvent::new(move || {
-            callback(&d.as_ref().get())
-        }));
-        e.as_ref().listen(self.d.as_ref());
-        self.callbacks.borrow_mut().push(e);
+        self.property().on_notify_with(snapshot, callback)
+    }
+    pub fn bind_bidirectional(&self, other: &RcProperty<'a, T>) -> (Connection<'a>, Connection<'a>)
+    where
+        T: Clone + PartialEq + 'a,
+    {
+        self.property().bind_bidirectional(other.property())
+    }
+    pub fn bind_bidirectional_with<U, F, B>(
+        &self,
+        other: &RcProperty<'a, U>,
+        forward: F,
+        backward: B,
+    ) -> (Connection<'a>, Connection<'a>)
+    where
+        T: PartialEq + 'a,
+        U: PartialEq + 'a,
+        F: Fn(&T) -> U + 'a,
+        B: Fn(&U) -> T + 'a,
+    {
+        self.property()
+            .bind_bidirectional_with(other.property(), forward, backward)
     }
 }
-impl<'a, T: Default + Clone> From<T> for Property<'a, T> {
-    fn from(t: T) -> Self {
-        let p = Property::default();
-        p.set(t);
+impl<'a, T: Default + 'a> RcProperty<'a, T> {
+    pub fn from_binding<F: PropertyBindingFn<T> + 'a>(f: F) -> RcProperty<'a, T> {
+        let p = RcProperty::default();
+        p.set_binding(f);
         p
     }
+    pub fn set_binding<F: PropertyBindingFn<T> + 'a>(&self, f: F) {
+        self.property().set_binding(f)
+    }
+    pub fn set_binding_if_changed<F: PropertyBindingFn<T> + 'a>(&self, f: F)
+    where
+        T: PartialEq,
+    {
+        self.property().set_binding_if_changed(f)
+    }
+}
+impl<'a, T: Clone> RcProperty<'a, T> {
+    // FIXME! remove
+    pub fn value(&self) -> T {
+        self.get()
+    }
+    pub fn get(&self) -> T {
+        self.property().get()
+    }
+}
+
+/// A function called when a Signal is emitted, with the arguments of the signal.
+pub trait SignalHandler<Args, R> {
+    /// Returns the reason of the failure if the handler could not run. See BindingErrorPolicy.
+    fn try_call(&self, args: Args) -> Result<R, String>;
+    fn description(&self) -> String {
+        String::default()
+    }
+}
+impl<F, Args, R> SignalHandler<Args, R> for F
+where
+    F: Fn(Args) -> R,
+{
+    fn try_call(&self, args: Args) -> Result<R, String> {
+        Ok((*self)(args))
+    }
+}
+// A handler which ignores the arguments, this is what rsml! generates.
+impl<F, Args, R> SignalHandler<Args, R> for (String, F)
+where
+    F: Fn() -> Option<R>,
+{
+    fn try_call(&self, _args: Args) -> Result<R, String> {
+        (self.1)().ok_or_else(|| "the handler returned None".to_owned())
+    }
+    fn description(&self) -> String {
+        (self.0).clone()
+    }
+}
+impl<F, Args, R, E> SignalHandler<Args, R> for Fallible<F>
+where
+    F: Fn(Args) -> Result<R, E>,
+    E: std::fmt::Display,
+{
+    fn try_call(&self, args: Args) -> Result<R, String> {
+        (self.0)(args).map_err(|e| e.to_string())
+    }
+}
+
+/// A Signal.
+/// `Args` is the type of the arguments passed to the handlers, use a tuple for several arguments.
+/// `R` is the type returned by the handlers, see `emit_with` to collect these values.
+pub struct Signal<'a, Args = (), R = ()> {
+    callbacks: Rc<Slots<Rc<dyn SignalHandler<Args, R> + 'a>>>,
+}
+
+impl<'a, Args, R> Default for Signal<'a, Args, R> {
+    fn default() -> Self {
+        Signal {
+            callbacks: Default::default(),
+        }
+    }
+}
+
+impl<'a, Args: 'a, R: 'a> Signal<'a, Args, R> {
+    pub fn set_binding<F: SignalHandler<Args, R> + 'a>(&self, f: F) -> Connection<'a> {
+        let id = self.callbacks.add(Rc::new(f));
+        Connection::new(&self.callbacks, id)
+    }
+
+    /// Same as set_binding, but helps the type inference of the closure arguments
+    pub fn connect<F: Fn(Args) -> R + 'a>(&self, f: F) -> Connection<'a> {
+        self.set_binding(f)
+    }
+
+    pub fn emit(&self, args: Args)
+    where
+        Args: Clone,
+    {
+        self.emit_with(args, (), |(), _| ())
+    }
+
+    /// Call all the handlers, and fold their return value with the accumulator.
+    pub fn emit_with<A, Acc>(&self, args: Args, init: A, mut accumulator: Acc) -> A
+    where
+        Args: Clone,
+        Acc: FnMut(A, R) -> A,
+    {
+        // Copy the list so the callbacks can connect or disconnect
+        let callbacks: Vec<_> = self
+            .callbacks
+            .slots
+            .borrow()
+            .iter()
+            .map(|x| x.1.clone())
+            .collect();
+        callbacks
+            .into_iter()
+            .fold(init, |acc, cb| match cb.try_call(args.clone()) {
+                Ok(r) => accumulator(acc, r),
+                Err(message) => {
+                    report_binding_error(BindingError {
+                        description: cb.description(),
+                        message,
+                    });
+                    acc
+                }
+            })
+    }
 }
 
 #[cfg(test)]
@@ -154,6 +961,7 @@ mod tests {
 
     use super::*;
     use std::cell::{Cell, RefCell};
+    use std::pin::pin;
     use std::rc::Rc;
 
     #[derive(Default)]
@@ -175,32 +983,39 @@ mod tests {
         area: Property<'a, u32>,
     }
 
-    /*
     impl<'a> Rectangle<'a> {
-        fn new()->Self {
-            Rectangle  { ..Default::default() }
+        fn width(self: Pin<&Self>) -> Pin<&Property<'a, u32>> {
+            unsafe { self.map_unchecked(|s| &s.width) }
+        }
+        fn height(self: Pin<&Self>) -> Pin<&Property<'a, u32>> {
+            unsafe { self.map_unchecked(|s| &s.height) }
         }
-    }*/
+        fn area(self: Pin<&Self>) -> Pin<&Property<'a, u32>> {
+            unsafe { self.map_unchecked(|s| &s.area) }
+        }
+    }
 
     #[test]
     fn it_works() {
-        let rec = Rc::new(RefCell::new(Rectangle::default()));
-        rec.borrow_mut().width = Property::from(2);
-        let wr = Rc::downgrade(&rec);
-        rec.borrow_mut().area = Property::from_binding(move || {
+        let rec = Rc::pin(Rectangle::default());
+        rec.as_ref().width().set(2);
+        let wr = pin_weak::rc::PinWeak::downgrade(rec.clone());
+        rec.as_ref().area().set_binding(move || {
             wr.upgrade()
-                .map(|wr| wr.borrow().width.value() * wr.borrow().height.value())
+                .map(|wr| wr.as_ref().width().value() * wr.as_ref().height().value())
                 .unwrap()
         });
-        rec.borrow().height.set(4);
-        assert_eq!(rec.borrow().area.value(), 4 * 2);
+        rec.as_ref().height().set(4);
+        assert_eq!(rec.as_ref().area().value(), 4 * 2);
     }
 
     #[test]
     fn test_notify() {
         let x = Cell::new(0);
-        let bar = Property::from(2);
-        let foo = Property::from(2);
+        let bar = pin!(Property::from(2));
+        let bar = bar.into_ref();
+        let foo = pin!(Property::from(2));
+        let foo = foo.into_ref();
         foo.on_notify(|_| x.set(x.get() + 1));
         foo.set(3);
         assert_eq!(x.get(), 1);
@@ -211,22 +1026,364 @@ mod tests {
         bar.set(8);
         assert_eq!(x.get(), 4);
     }
-}
 
-/// A Signal.
-#[derive(Default)]
-pub struct Signal<'a> {
-    callbacks: RefCell<Vec<Box<dyn PropertyBindingFn<()> + 'a>>>,
-}
+    #[test]
+    fn test_notify_set() {
+        // A callback clamping the value of its own property
+        let p = RcProperty::from(1);
+        let weak = p.as_weak();
+        p.on_notify(move |v| {
+            if *v > 5 {
+                weak.with_property(|p| p.set(5));
+            }
+        });
+        p.set(8);
+        assert_eq!(p.get(), 5);
+        p.set(3);
+        assert_eq!(p.get(), 3);
+
+        let text = RcProperty::from(String::new());
+        let weak = text.as_weak();
+        text.on_notify_with(String::len, move |len| {
+            if len > 3 {
+                weak.with_property(|p| p.set("abc".into()));
+            }
+        });
+        text.set("hello".into());
+        assert_eq!(text.get(), "abc");
+    }
 
-impl<'a> Signal<'a> {
-    pub fn set_binding<F: PropertyBindingFn<()> + 'a>(&self, f: F) {
-        self.callbacks.borrow_mut().push(Box::new(f));
+    #[test]
+    fn test_remove_binding() {
+        let bar = RcProperty::from(2);
+        let foo = RcProperty::from_binding(|| bar.get() * 10);
+        assert_eq!(foo.get(), 20);
+        bar.set(3);
+        foo.property().remove_binding();
+        assert_eq!(foo.get(), 20);
+        bar.set(4);
+        assert_eq!(foo.get(), 20);
     }
 
-    pub fn emit(&self) {
-        for cb in self.callbacks.borrow_mut().iter_mut() {
-            cb.run();
-        }
+    #[test]
+    fn test_if_changed() {
+        let x = Cell::new(0);
+        let bar = pin!(Property::from(2));
+        let bar = bar.into_ref();
+        let foo = pin!(Property::default());
+        let foo = foo.into_ref();
+        foo.set_binding_if_changed(|| bar.value() % 2);
+        foo.on_notify(|_| x.set(x.get() + 1));
+        bar.set(4);
+        assert_eq!(x.get(), 0);
+        bar.set(5);
+        assert_eq!(x.get(), 1);
+        foo.set_if_changed(1);
+        assert_eq!(x.get(), 1);
+        foo.set_if_changed(8);
+        assert_eq!(x.get(), 2);
+    }
+
+    #[test]
+    fn test_connection() {
+        let x = Cell::new(0);
+        let foo = RcProperty::from(2);
+        let c1 = foo.on_notify(|_| x.set(x.get() + 1));
+        let c2 = foo.on_notify(|_| x.set(x.get() + 10)).scoped();
+        foo.set(3);
+        assert_eq!(x.get(), 11);
+        assert!(c1.is_connected());
+        c1.disconnect();
+        assert!(!c1.is_connected());
+        foo.set(4);
+        assert_eq!(x.get(), 21);
+        core::mem::drop(c2);
+        foo.set(5);
+        assert_eq!(x.get(), 21);
+        let c3 = foo.on_notify(|_| x.set(x.get() + 100)).scoped().release();
+        foo.set(6);
+        assert_eq!(x.get(), 121);
+        assert!(c3.is_connected());
+
+        let signal = Signal::default();
+        let c = signal.connect(|()| x.set(x.get() + 1));
+        signal.emit(());
+        assert_eq!(x.get(), 122);
+        c.disconnect();
+        signal.emit(());
+        assert_eq!(x.get(), 122);
+    }
+
+    #[test]
+    fn test_disconnect_from_callback() {
+        let x = Cell::new(0);
+        let x = &x;
+        let foo = RcProperty::from(2);
+        let conn: Rc<RefCell<Option<Connection>>> = Default::default();
+        let scoped: Rc<RefCell<Option<ScopedConnection>>> = Default::default();
+        let step = Box::new(1);
+        let c = {
+            let conn = conn.clone();
+            foo.on_notify(move |_| {
+                conn.borrow().as_ref().unwrap().disconnect();
+                // the callback is still alive until it returns
+                x.set(x.get() + *step);
+            })
+        };
+        *conn.borrow_mut() = Some(c.clone());
+        let step = Box::new(100);
+        *scoped.borrow_mut() = Some({
+            let scoped = scoped.clone();
+            foo.on_notify(move |_| {
+                core::mem::drop(scoped.borrow_mut().take());
+                x.set(x.get() + *step);
+            })
+            .scoped()
+        });
+        foo.set(3);
+        assert_eq!(x.get(), 101);
+        assert!(!c.is_connected());
+        assert!(scoped.borrow().is_none());
+        foo.set(4);
+        assert_eq!(x.get(), 101);
+    }
+
+    #[test]
+    fn test_weak() {
+        let x = Cell::new(0);
+        let weak = {
+            let owner = Rc::pin(Property::from(2));
+            let foo = owner.as_ref();
+            foo.on_notify(|v| x.set(*v));
+            let weak = foo.as_weak();
+            // Does nothing while the property has no owner
+            weak.set(4);
+            assert_eq!((weak.get(), foo.get()), (None, 2));
+            foo.set_owner(&owner);
+            weak.set(5);
+            assert_eq!(foo.get(), 5);
+            assert_eq!(x.get(), 5);
+            weak
+        };
+        assert_eq!(weak.get(), None);
+        weak.set(8);
+        assert_eq!(x.get(), 5);
+        assert_eq!(WeakProperty::<u32>::default().get(), None);
+    }
+
+    #[test]
+    fn test_weak_drop_owner() {
+        let holder = Rc::new(RefCell::new(None));
+        let prop = RcProperty::from(1);
+        let weak = prop.as_weak();
+        let h = holder.clone();
+        // Drops the property from its own callback, called through the WeakProperty
+        prop.on_notify(move |_| drop(h.borrow_mut().take()));
+        *holder.borrow_mut() = Some(prop);
+        weak.set(2);
+        assert!(holder.borrow().is_none());
+        assert_eq!(weak.get(), None);
+
+        let prop = RcProperty::from(1);
+        let weak = prop.as_weak();
+        let h = holder.clone();
+        prop.set_binding(move || {
+            drop(h.borrow_mut().take());
+            3
+        });
+        *holder.borrow_mut() = Some(prop);
+        assert_eq!(weak.get(), Some(3));
+        assert_eq!(weak.get(), None);
+    }
+
+    #[test]
+    fn test_not_clone() {
+        #[derive(Default, PartialEq)]
+        struct Text(String);
+        let text = RcProperty::<Text>::default();
+        let len = RcProperty::from_binding(|| text.with(|t| t.0.len()));
+        let changes = Rc::new(Cell::new(0));
+        let c = changes.clone();
+        text.on_notify_with(|t| t.0.len(), move |len| c.set(len));
+        text.set(Text("hello".into()));
+        assert_eq!(len.get(), 5);
+        assert_eq!(changes.get(), 5);
+        assert_eq!(text.borrow().0, "hello");
+        text.set_if_changed(Text("hello".into()));
+        let copy = RcProperty::<Text>::default();
+        copy.property().bind_bidirectional_with(
+            text.property(),
+            |t| Text(t.0.clone()),
+            |t| Text(t.0.clone()),
+        );
+        assert_eq!(text.borrow().0, "");
+        copy.set(Text("world".into()));
+        assert_eq!(text.borrow().0, "world");
+        assert_eq!(len.get(), 5);
+    }
+
+    #[test]
+    fn test_read_only() {
+        let notified = Cell::new(0);
+        let count = RcProperty::from(1);
+        let read_only = count.as_read_only();
+        let double = RcProperty::from_binding(move || read_only.get() * 2);
+        read_only.on_notify(|v| notified.set(*v));
+        count.set(4);
+        assert_eq!(double.get(), 8);
+        assert_eq!(notified.get(), 4);
+        assert_eq!(*read_only.borrow(), 4);
+    }
+
+    #[test]
+    fn test_bind_bidirectional() {
+        let (c, a, b) = (
+            RcProperty::from(10),
+            RcProperty::from(1),
+            RcProperty::from(2),
+        );
+        let (c, a, b) = (c.property(), a.property(), b.property());
+        let count = Rc::new(Cell::new(0));
+        let count2 = count.clone();
+        b.on_notify(move |_| count2.set(count2.get() + 1));
+        a.bind_bidirectional(b);
+        assert_eq!(b.get(), 1);
+        a.set(5);
+        assert_eq!(b.get(), 5);
+        b.set(6);
+        assert_eq!(a.get(), 6);
+        assert_eq!(count.get(), 3);
+
+        // The binding of `b` is kept until `a` changes
+        b.set_binding(move || c.get() + 1);
+        assert_eq!(a.get(), 11);
+        c.set(20);
+        assert_eq!(a.get(), 21);
+        a.set(3);
+        c.set(30);
+        assert_eq!(b.get(), 3);
+
+        let celsius = RcProperty::from(0.);
+        let fahrenheit = RcProperty::from(0.);
+        celsius.bind_bidirectional_with(
+            &fahrenheit,
+            |c| c * 9. / 5. + 32.,
+            |f| (f - 32.) * 5. / 9.,
+        );
+        assert_eq!(fahrenheit.get(), 32.);
+        fahrenheit.set(212.);
+        assert_eq!(celsius.get(), 100.);
+        celsius.set(-40.);
+        assert_eq!(fahrenheit.get(), -40.);
+    }
+
+    #[test]
+    fn test_alias() {
+        let x = Cell::new(0);
+        let bar = RcProperty::from(2);
+        let foo = RcProperty::from(1);
+        let alias = foo.alias();
+        assert_eq!(alias.get(), 1);
+        alias.on_notify(|_| x.set(x.get() + 1));
+        alias.set(3);
+        assert_eq!(foo.get(), 3);
+        foo.set(4);
+        assert_eq!(alias.get(), 4);
+        assert_eq!(x.get(), 2);
+        alias.set_binding(|| bar.get() * 10);
+        assert_eq!(foo.get(), 20);
+        bar.set(5);
+        assert_eq!(foo.get(), 50);
+        assert_eq!(x.get(), 4);
+
+        let rec = Rc::pin(Rectangle::default());
+        let width = PropertyRef::new(rec.clone(), Rectangle::width);
+        core::mem::drop(rec);
+        width.get().set(8);
+        assert_eq!(width.get().get(), 8);
+        let detached = PropertyRef::new_or_detached(None::<Pin<Rc<Rectangle>>>, Rectangle::width);
+        detached.get().set(3);
+        assert_eq!(detached.get().get(), 3);
+    }
+
+    #[test]
+    fn test_signal() {
+        let sum = Cell::new(0);
+        let signal: Signal<(u32, u32), u32> = Signal::default();
+        signal.connect(|(a, b)| {
+            sum.set(sum.get() + a + b);
+            a * b
+        });
+        signal.set_binding(("ignore args".to_owned(), || Some(100)));
+        signal.emit((2, 3));
+        assert_eq!(sum.get(), 5);
+        assert_eq!(signal.emit_with((4, 5), 0, |acc, r| acc + r), 120);
+        assert_eq!(sum.get(), 14);
+        let max = signal.emit_with((1, 1), None, |acc: Option<u32>, r| acc.max(Some(r)));
+        assert_eq!(max, Some(100));
+        // The handlers which fail are skipped
+        signal.set_binding(("fails".to_owned(), || None));
+        signal.set_binding(Fallible(
+            |(a, _)| if a > 1 { Ok(a) } else { Err("too small") },
+        ));
+        assert_eq!(signal.emit_with((1, 1), 0, |acc, r| acc + r), 101);
+        assert_eq!(signal.emit_with((2, 1), 0, |acc, r| acc + r), 104);
+    }
+
+    #[test]
+    fn test_binding_error() {
+        let errors = Rc::new(RefCell::new(Vec::new()));
+        let errors2 = errors.clone();
+        set_binding_error_policy(BindingErrorPolicy::Hook(Rc::new(move |e| {
+            errors2.borrow_mut().push(e.to_string())
+        })));
+        let a = RcProperty::from(4u32);
+        let b = RcProperty::<u32>::default();
+        let wa = a.as_weak();
+        b.set_binding(("b".to_owned(), move || wa.get()?.checked_sub(2)));
+        assert_eq!(b.get(), 2);
+        a.set(1);
+        assert_eq!(b.get(), 2);
+        assert_eq!(
+            *errors.borrow(),
+            vec!["Binding b failed: the binding returned None"]
+        );
+        a.set(5);
+        assert_eq!(b.get(), 3);
+
+        let c = RcProperty::<u32>::default();
+        let wa = a.as_weak();
+        c.set_binding(Fallible(move || {
+            wa.get().unwrap_or(0).to_string().parse::<u32>()
+        }));
+        assert_eq!(c.get(), 5);
+
+        set_binding_error_policy(BindingErrorPolicy::UseDefault);
+        a.set(1);
+        assert_eq!(b.get(), 0);
+        set_binding_error_policy(BindingErrorPolicy::KeepValue);
+
+        let d = RcProperty::<i32>::from(7);
+        d.set_binding(Fallible(|| "x".parse::<i32>()));
+        assert_eq!(d.get(), 7);
+    }
+
+    #[test]
+    fn test_binding_loop() {
+        let chain = Rc::new(RefCell::new(Vec::new()));
+        let chain2 = chain.clone();
+        set_binding_loop_policy(BindingLoopPolicy::Hook(Rc::new(move |e| {
+            *chain2.borrow_mut() = e.chain.clone()
+        })));
+        let a = RcProperty::<u32>::default();
+        let b = RcProperty::<u32>::default();
+        let c = RcProperty::<u32>::default();
+        let (wa, wb, wc) = (a.as_weak(), b.as_weak(), c.as_weak());
+        a.set_binding(("a".to_owned(), move || Some(wb.get()? + 1)));
+        b.set_binding(("b".to_owned(), move || Some(wc.get()? + 1)));
+        c.set_binding(("c".to_owned(), move || Some(wa.get()? + 1)));
+        assert_eq!(a.get(), 3);
+        assert_eq!(*chain.borrow(), vec!["a", "b", "c", "a"]);
+        set_binding_loop_policy(BindingLoopPolicy::Panic);
     }
 }
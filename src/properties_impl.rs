@@ -1,9 +1,47 @@
 //! The new implementation of the property system.
 //! Which requires no memory allocation and use Pin to stay safe
+//!
+//! This module only depends on `core` and `alloc`. Without the `std` feature, there are no
+//! thread locals, and `set_engine_state_accessor` must be called before using any property.
+//!
+//! `Property::get` clones the value. `Property::with` and `Property::borrow` give a reference to
+//! it instead, and are the only way to read the types which are not Clone. The value cannot be
+//! replaced while it is borrowed: `set`, or the re-evaluation of the binding, panics.
+//!
+//! With the `tracing` feature, the evaluation of the bindings, the calls to the ChangeEvent and
+//! the changes of the properties are reported to the `tracing` subscriber, at the TRACE level
+//! under the `propertybindings::properties_impl` target.
+//!
+//! With the `derive` feature, `#[derive(Properties)]` generates, for each `Property<T>` field
+//! `foo` of a struct, a pinned projection `foo(self: Pin<&Self>) -> Pin<&Property<T>>`, and the
+//! `get_foo`, `set_foo` and `set_foo_binding` accessors, `get_foo` only if `T` is Clone. The
+//! struct must not implement Drop nor Unpin.
+//! Together with `binding!` and `pin_mut!` from pin-utils, no unsafe code is needed:
+//!
+//! ```ignore
+//! #[derive(Properties, Default)]
+//! struct Item {
+//!     width: Property<f32>,
+//!     height: Property<f32>,
+//!     area: Property<f32>,
+//! }
+//!
+//! let item = Item::default();
+//! pin_utils::pin_mut!(item);
+//! let item = item.as_ref();
+//! binding!(area = move || item.get_width() * item.get_height());
+//! item.set_area_binding(area);
+//! ```
 
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::rc::Rc;
+use alloc::string::String;
+use alloc::vec::Vec;
 use core::cell::{Cell, RefCell};
 use core::default::Default;
 use core::marker::PhantomData;
+use core::mem::MaybeUninit;
 use core::ops::DerefMut;
 use core::pin::Pin;
 use core::ptr::NonNull;
@@ -20,32 +58,71 @@ mod internal {
     }
 }
 
+/// Emit a `tracing` event, if the feature is enabled. The fields are only evaluated if the
+/// event is enabled.
+#[cfg(feature = "tracing")]
+macro_rules! trace_event {
+    ($($t:tt)*) => { tracing::trace!($($t)*) };
+}
+#[cfg(not(feature = "tracing"))]
+macro_rules! trace_event {
+    ($($t:tt)*) => {};
+}
+
 #[path = "double_link.rs"]
 mod double_link;
 
+#[path = "graph.rs"]
+pub mod graph;
+
 enum NotifyList {}
 enum SenderList {}
+enum PendingList {}
 
 struct DependencyNode {
     notify_list: double_link::Node<NotifyList>,
     sender_list: double_link::Node<SenderList>,
+    /// Linked in the pending events of the EngineState while the ChangeEvent is waiting to be emitted
+    pending_list: double_link::Node<PendingList>,
+    /// While pending: whether the source is known to have changed, or if it only might have.
+    pending_dirty: Cell<bool>,
+    /// The property which notifies (the node is in its notify list, so it outlives the node)
+    source: NonNull<dyn PropertyBase>,
     elem: NonNull<dyn NotificationReciever>,
 }
 impl DependencyNode {
-    fn new(elem: NonNull<dyn NotificationReciever>) -> Self {
+    fn new(source: NonNull<dyn PropertyBase>, elem: NonNull<dyn NotificationReciever>) -> Self {
         DependencyNode {
             notify_list: double_link::Node::default(),
             sender_list: double_link::Node::default(),
+            pending_list: double_link::Node::default(),
+            pending_dirty: Cell::new(false),
+            source,
             elem,
         }
     }
 }
 
+/// The state of a binding.
+///
+/// When a property changes, the bindings which directly depend on it become `Dirty`, and the
+/// ones that depend on those become `MaybeDirty`: they only need to be re-evaluated if one of
+/// their dependencies actually changes once it is brought up to date.
+#[derive(Clone, Copy, PartialEq, PartialOrd)]
+enum DirtyState {
+    Clean,
+    MaybeDirty,
+    Dirty,
+}
+
 impl double_link::LinkedList for NotifyList {
     type NodeItem = DependencyNode;
     unsafe fn next_ptr(mut node: NonNull<Self::NodeItem>) -> NonNull<double_link::Node<Self>> {
         NonNull::new_unchecked(&mut node.as_mut().notify_list as *mut _)
     }
+    unsafe fn free(node: NonNull<Self::NodeItem>) {
+        free_node(node)
+    }
 }
 
 impl double_link::LinkedList for SenderList {
@@ -53,66 +130,385 @@ impl double_link::LinkedList for SenderList {
     unsafe fn next_ptr(mut node: NonNull<Self::NodeItem>) -> NonNull<double_link::Node<Self>> {
         NonNull::new_unchecked(&mut node.as_mut().sender_list as *mut _)
     }
+    unsafe fn free(node: NonNull<Self::NodeItem>) {
+        free_node(node)
+    }
+}
+
+impl double_link::LinkedList for PendingList {
+    type NodeItem = DependencyNode;
+    unsafe fn next_ptr(mut node: NonNull<Self::NodeItem>) -> NonNull<double_link::Node<Self>> {
+        NonNull::new_unchecked(&mut node.as_mut().pending_list as *mut _)
+    }
+}
+
+/// The nodes are owned by the property and the ChangeEvent, so only unlink them.
+struct PendingEvents(double_link::Head<PendingList>);
+impl Drop for PendingEvents {
+    fn drop(&mut self) {
+        while self.0.take_first().is_some() {}
+    }
+}
+
+/// The state of the engine for one thread: the binding being evaluated, and the pending events.
+///
+/// With the `std` feature, there is one per thread. Otherwise, it is provided by the function
+/// given to `set_engine_state_accessor`.
+pub struct EngineState {
+    current_property: RefCell<Option<Pin<&'static dyn NotificationReciever>>>,
+    pending_events: RefCell<PendingEvents>,
+    /// Number of nested batches, including the one from emit_pending_events itself.
+    batch_depth: Cell<usize>,
+    binding_loop_policy: RefCell<BindingLoopPolicy>,
+    evaluation_stack: Cell<*const EvaluationFrame>,
+    /// The memory of the freed DependencyNode, reused by alloc_node
+    /// (the nodes are boxed because they are handed out individually)
+    #[allow(clippy::vec_box)]
+    node_pool: RefCell<Vec<Box<MaybeUninit<DependencyNode>>>>,
+    node_pool_capacity: Cell<usize>,
+}
+
+impl EngineState {
+    pub const fn new() -> Self {
+        EngineState {
+            current_property: RefCell::new(None),
+            pending_events: RefCell::new(PendingEvents(double_link::Head::new())),
+            batch_depth: Cell::new(0),
+            binding_loop_policy: RefCell::new(BindingLoopPolicy::Panic),
+            evaluation_stack: Cell::new(core::ptr::null()),
+            node_pool: RefCell::new(Vec::new()),
+            node_pool_capacity: Cell::new(DEFAULT_NODE_POOL_CAPACITY),
+        }
+    }
+}
+
+impl Default for EngineState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "std")]
+thread_local!(static STATE: EngineState = const { EngineState::new() });
+
+#[cfg(feature = "std")]
+fn with_state<R>(f: impl FnOnce(&EngineState) -> R) -> R {
+    STATE.with(f)
+}
+
+/// Same as with_state, but returns None if the thread local was already destroyed.
+#[cfg(feature = "std")]
+fn try_with_state<R>(f: impl FnOnce(&EngineState) -> R) -> Option<R> {
+    STATE.try_with(f).ok()
+}
+
+#[cfg(not(feature = "std"))]
+static STATE_ACCESSOR: core::sync::atomic::AtomicPtr<()> =
+    core::sync::atomic::AtomicPtr::new(core::ptr::null_mut());
+
+/// Set the function which returns the EngineState of the current thread or execution context.
+///
+/// On a single threaded target, it can return a static. Since EngineState is not Sync, such a
+/// static needs a wrapper which implements Sync.
+///
+/// # Safety
+/// The returned state must not be used by several threads at the same time, and must stay
+/// the same for as long as properties are in use on the current thread.
+#[cfg(not(feature = "std"))]
+pub unsafe fn set_engine_state_accessor(accessor: fn() -> &'static EngineState) {
+    STATE_ACCESSOR.store(accessor as *mut (), core::sync::atomic::Ordering::Release);
+}
+
+#[cfg(not(feature = "std"))]
+fn with_state<R>(f: impl FnOnce(&EngineState) -> R) -> R {
+    let accessor = STATE_ACCESSOR.load(core::sync::atomic::Ordering::Acquire);
+    assert!(
+        !accessor.is_null(),
+        "set_engine_state_accessor must be called before using properties"
+    );
+    let accessor =
+        unsafe { core::mem::transmute::<*mut (), fn() -> &'static EngineState>(accessor) };
+    f(accessor())
+}
+
+#[cfg(not(feature = "std"))]
+fn try_with_state<R>(f: impl FnOnce(&EngineState) -> R) -> Option<R> {
+    Some(with_state(f))
+}
+
+const DEFAULT_NODE_POOL_CAPACITY: usize = 1024;
+
+/// Set how many freed dependency nodes are kept in this thread to be reused.
+///
+/// A node is allocated each time a binding reads a property, and freed when the binding is
+/// re-evaluated. A capacity of 0 disables the pool.
+pub fn set_node_pool_capacity(capacity: usize) {
+    with_state(|s| {
+        s.node_pool_capacity.set(capacity);
+        s.node_pool.borrow_mut().truncate(capacity);
+    });
+}
+
+fn alloc_node(
+    source: NonNull<dyn PropertyBase>,
+    elem: NonNull<dyn NotificationReciever>,
+) -> NonNull<DependencyNode> {
+    let node = DependencyNode::new(source, elem);
+    let ptr = match with_state(|s| s.node_pool.borrow_mut().pop()) {
+        Some(mut b) => {
+            unsafe { b.as_mut_ptr().write(node) };
+            Box::into_raw(b) as *mut DependencyNode
+        }
+        None => Box::into_raw(Box::new(node)),
+    };
+    unsafe { NonNull::new_unchecked(ptr) }
+}
+
+/// Free a node allocated with alloc_node. It is unlinked from all its lists.
+unsafe fn free_node(node: NonNull<DependencyNode>) {
+    core::ptr::drop_in_place(node.as_ptr());
+    let b = Box::from_raw(node.as_ptr() as *mut MaybeUninit<DependencyNode>);
+    // If the state is gone, the box is simply dropped with the closure
+    try_with_state(move |s| {
+        let mut pool = s.node_pool.borrow_mut();
+        if pool.len() < s.node_pool_capacity.get() {
+            pool.push(b);
+        }
+    });
+}
+
+/// Increment the batch depth for its lifetime, also when unwinding.
+struct BatchGuard;
+impl BatchGuard {
+    fn new() -> Self {
+        with_state(|s| s.batch_depth.set(s.batch_depth.get() + 1));
+        BatchGuard
+    }
+}
+impl Drop for BatchGuard {
+    fn drop(&mut self) {
+        with_state(|s| s.batch_depth.set(s.batch_depth.get() - 1));
+    }
+}
+
+/// Emit the ChangeEvents which were queued while the bindings were marked dirty.
+///
+/// This is done in a second phase, once the whole graph has been marked, so that neither the
+/// callbacks nor the bindings they evaluate can observe a partially updated graph. Every binding
+/// is then evaluated at most once per change.
+fn emit_pending_events() {
+    if with_state(|s| s.batch_depth.get()) > 0 {
+        // The outer call will emit the events we might have added
+        return;
+    }
+    let _guard = BatchGuard::new();
+    while let Some(node) = with_state(|s| s.pending_events.borrow_mut().0.take_first()) {
+        unsafe { Pin::new_unchecked(node.as_ref().elem.as_ref()).emit(node) };
+    }
 }
 
-thread_local!(static CURRENT_PROPERTY: RefCell<Option<Pin<&'static dyn NotificationReciever>>>
-    = Default::default());
+/// Run `f`, and defer the ChangeEvent callbacks until the outermost batch returns, so they
+/// are called only once even if several of the properties they depend on were set.
+///
+/// The properties still mark their dependencies dirty right away, so reading a property within
+/// the batch gives its up to date value. If `f` panics, the pending callbacks are emitted
+/// with the next change.
+pub fn batch<R, F: FnOnce() -> R>(f: F) -> R {
+    let r = {
+        let _guard = BatchGuard::new();
+        f()
+    };
+    emit_pending_events();
+    r
+}
 
 fn run_with_current<U, F>(dep: Pin<&dyn NotificationReciever>, f: F) -> U
 where
     F: Fn() -> U,
 {
-    let mut old = Some(unsafe {
+    // Restore the previous value, even if f panics
+    struct Restore(Option<Pin<&'static dyn NotificationReciever>>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            with_state(|s| {
+                let mut m = s.current_property.borrow_mut();
+                core::mem::swap(m.deref_mut(), &mut self.0);
+            });
+        }
+    }
+    let mut old = Restore(Some(unsafe {
         // This is safe because we only store it for the duration of the call
         core::mem::transmute::<Pin<&dyn NotificationReciever>, Pin<&'static dyn NotificationReciever>>(
             dep,
         )
+    }));
+    with_state(|s| {
+        let mut m = s.current_property.borrow_mut();
+        core::mem::swap(m.deref_mut(), &mut old.0);
     });
-    CURRENT_PROPERTY.with(|cur_dep| {
-        let mut m = cur_dep.borrow_mut();
-        core::mem::swap(m.deref_mut(), &mut old);
-    });
-    let res = f();
-    CURRENT_PROPERTY.with(|cur_dep| {
-        let mut m = cur_dep.borrow_mut();
-        core::mem::swap(m.deref_mut(), &mut old);
-        //assert_eq!(old, Some(dep));
-    });
-    res
+    f()
+}
+
+/// A binding loop: the chain of the properties which depend on each other, starting and
+/// ending with the same property.
+#[derive(Debug, Clone)]
+pub struct BindingLoopError {
+    pub chain: Vec<String>,
+}
+
+impl core::fmt::Display for BindingLoopError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "Binding loop detected: {}", self.chain.join(" -> "))
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for BindingLoopError {}
+
+/// What to do when a binding loop is detected.
+/// In all cases but Panic, the property involved in the loop keeps its previous value.
+#[derive(Clone, Default)]
+pub enum BindingLoopPolicy {
+    /// Panic with the description of the loop (the default)
+    #[default]
+    Panic,
+    /// Print the description of the loop on stderr
+    #[cfg(feature = "std")]
+    Log,
+    /// Call the given function
+    Hook(Rc<dyn Fn(&BindingLoopError)>),
+}
+
+/// Change what happens when a binding loop is detected in this thread.
+pub fn set_binding_loop_policy(policy: BindingLoopPolicy) {
+    with_state(|s| *s.binding_loop_policy.borrow_mut() = policy);
+}
+
+/// The properties being currently evaluated, linked from the innermost one.
+/// Used to report the chain of properties involved in a binding loop.
+struct EvaluationFrame {
+    property: *const dyn PropertyBase,
+    parent: *const EvaluationFrame,
+}
+
+fn describe(p: &dyn PropertyBase) -> String {
+    let d = p.description();
+    if d.is_empty() {
+        format!("{:p}", p as *const dyn PropertyBase as *const ())
+    } else {
+        d
+    }
+}
+
+/// Called when `p` is accessed while it is being evaluated.
+fn binding_loop_detected(p: &dyn PropertyBase) {
+    let p = p as *const dyn PropertyBase as *const ();
+    let mut chain = Vec::new();
+    let mut frame = with_state(|s| s.evaluation_stack.get());
+    while let Some(f) = unsafe { frame.as_ref() } {
+        chain.push(describe(unsafe { &*f.property }));
+        if f.property as *const () == p {
+            break;
+        }
+        frame = f.parent;
+    }
+    chain.reverse();
+    if let Some(first) = chain.first().cloned() {
+        chain.push(first);
+    }
+    let error = BindingLoopError { chain };
+    match with_state(|s| s.binding_loop_policy.borrow().clone()) {
+        BindingLoopPolicy::Panic => panic!("{}", error),
+        #[cfg(feature = "std")]
+        BindingLoopPolicy::Log => eprintln!("{}", error),
+        BindingLoopPolicy::Hook(f) => f(&error),
+    }
 }
 
 trait NotificationReciever {
-    fn notify(self: Pin<&Self>, from: Pin<&dyn PropertyBase>);
+    /// `dirty` is true if the source of the node has changed, and false if it might have
+    /// changed, depending on the result of the evaluation of its binding.
+    fn notify(self: Pin<&Self>, node: NonNull<DependencyNode>, dirty: bool);
     fn add_rev_dependency(self: Pin<&Self>, link: NonNull<DependencyNode>);
+    /// Called from emit_pending_events, for the receivers which queued themselves in notify.
+    fn emit(self: Pin<&Self>, _node: NonNull<DependencyNode>) {}
+    /// For the graph introspection
+    fn as_property(&self) -> Option<&dyn PropertyBase> {
+        None
+    }
 }
 
 trait PropertyBase {
     fn add_dependency(&self, link: NonNull<DependencyNode>);
+    /// The node which was added last with add_dependency, if it is still there
+    fn first_dependency(&self) -> Option<NonNull<DependencyNode>>;
     //    fn update_dependencies(&self);
 
+    /// Re-evaluate the binding if it is dirty, so that the property is connected again
+    /// to the properties it depends on.
+    fn update(self: Pin<&Self>);
+
     /// For debug purposes only
     fn description(&self) -> String {
         String::default()
     }
+    fn has_binding(&self) -> bool;
+    /// Call `f` with the properties the binding depends on
+    fn visit_dependencies(&self, f: &mut dyn FnMut(NonNull<dyn PropertyBase>));
+    /// Call `f` with the properties and ChangeEvent depending on this one
+    fn visit_dependents(&self, f: &mut dyn FnMut(NonNull<dyn NotificationReciever>));
 
-    fn accessed(&self) -> bool {
-        CURRENT_PROPERTY.with(|cur_dep| {
-            if let Some(m) = *cur_dep.borrow() {
-                let b = Box::new(DependencyNode::new((&*m).into()));
-                let b = unsafe { NonNull::new_unchecked(Box::into_raw(b)) };
-
-                self.add_dependency(b);
-                m.as_ref().add_rev_dependency(b);
-                true
-            } else {
-                false
+    fn accessed(&self) -> bool
+    where
+        Self: Sized,
+    {
+        let current = with_state(|s| *s.current_property.borrow());
+        if let Some(m) = current {
+            let elem: NonNull<dyn NotificationReciever> = (&*m).into();
+            // The nodes are prepended, so when the binding reads this property again, the
+            // node from the previous read is usually still the first one. Only that one is
+            // checked, deliberately: scanning the list would make each read linear in the
+            // number of dependents. A binding reading this property again after another one
+            // gets a duplicate node, which only notifies it twice, and the nodes are all
+            // released when the binding is re-evaluated.
+            if let Some(first) = self.first_dependency() {
+                if unsafe { first.as_ref() }.elem.cast::<()>() == elem.cast::<()>() {
+                    return true;
+                }
             }
-        })
+            // The node is removed from our notify list when we are dropped
+            let source = unsafe {
+                core::mem::transmute::<&dyn PropertyBase, &(dyn PropertyBase + 'static)>(self)
+            };
+            let b = alloc_node(source.into(), elem);
+            self.add_dependency(b);
+            m.as_ref().add_rev_dependency(b);
+            true
+        } else {
+            false
+        }
     }
 }
 
 pub trait Binding<T> {
     fn call(self: Pin<&Self>) -> T;
+
+    /// Called instead of `call` to evaluate the binding. If it returns None, the property keeps
+    /// its current value and the properties depending on it are not notified.
+    fn try_call(self: Pin<&Self>) -> Option<T> {
+        Some(self.call())
+    }
+
+    /// For debug purposes only
+    fn description(&self) -> String {
+        String::default()
+    }
+
+    /// Called after the binding was re-evaluated. If it returns false, the properties depending
+    /// on this one are not notified.
+    fn has_changed(self: Pin<&Self>, _old: &T, _new: &T) -> bool {
+        true
+    }
 }
 
 impl<F, T> Binding<T> for F
@@ -124,6 +520,43 @@ where
     }
 }
 
+/// Wraps a binding so that the properties and ChangeEvent depending on it are only notified
+/// if the new value is not equal to the previous one.
+pub struct IfChanged<B>(pub B);
+
+impl<T: PartialEq, B: Binding<T>> Binding<T> for IfChanged<B> {
+    fn call(self: Pin<&Self>) -> T {
+        unsafe { self.map_unchecked(|s| &s.0) }.call()
+    }
+
+    fn try_call(self: Pin<&Self>) -> Option<T> {
+        unsafe { self.map_unchecked(|s| &s.0) }.try_call()
+    }
+
+    fn has_changed(self: Pin<&Self>, old: &T, new: &T) -> bool {
+        old != new
+    }
+
+    fn description(&self) -> String {
+        self.0.description()
+    }
+}
+
+#[cfg(feature = "derive")]
+pub use propertybindings_derive::Properties;
+
+/// Declare a variable holding a `BindingStorage` pinned on the stack, to be given to
+/// `Property::set_binding`.
+#[macro_export]
+macro_rules! binding {
+    ($name:ident = $binding:expr) => {
+        let $name = $crate::properties_impl::BindingStorage::new($binding);
+        // Shadows the storage so it can't be moved
+        #[allow(unused_unsafe)]
+        let $name = unsafe { ::core::pin::Pin::new_unchecked(&$name) };
+    };
+}
+
 #[repr(C)]
 pub struct BindingStorage<B: ?Sized> {
     vtable: *const (),
@@ -134,6 +567,14 @@ pub struct BindingStorage<B: ?Sized> {
     // TODO: have static node, also no need for double link
     notify_dep: Cell<double_link::Head<NotifyList>>,
 
+    /// Set when one of the dependencies has changed and the binding needs to be re-evaluated
+    /// the next time the property is accessed.
+    dirty: Cell<DirtyState>,
+    /// Set while the binding is being evaluated, to detect binding loops
+    evaluating: Cell<bool>,
+    /// Re-evaluated when its dependencies change instead of when accessed, see `set_eager`
+    eager: Cell<bool>,
+
     // rev and rev_dep goes here
     binding: B,
 }
@@ -150,6 +591,9 @@ impl<B> BindingStorage<B> {
             vtable,
             rev_dep: Default::default(),
             notify_dep: Default::default(),
+            dirty: Cell::new(DirtyState::Dirty),
+            evaluating: Cell::new(false),
+            eager: Cell::new(false),
             binding,
         }
     }
@@ -279,8 +723,14 @@ impl<T> PropertyInternal<T> {
     unsafe fn remove_binding<'a>(&'a self) {
         let v = self.value.get();
         if let Some(b) = self.binding() {
+            trace_event!(
+                property = ?(self as *const Self),
+                description = %b.as_ref().description(),
+                "remove_binding"
+            );
             self.value.set(0);
             (*self.notify_dep().as_ptr()).swap(&mut *b.notify_dep.as_ptr());
+            (*b.rev_dep.as_ptr()).clear();
             if v & 0b11 == 0b11 {
                 b.drop_binding();
             }
@@ -301,6 +751,7 @@ impl<T> Drop for PropertyInternal<T> {
     fn drop(&mut self) {
         unsafe {
             self.remove_binding();
+            (*self.notify_dep().as_ptr()).clear();
         }
     }
 }
@@ -309,65 +760,275 @@ impl<T> Drop for PropertyInternal<T> {
 #[repr(C)]
 pub struct Property<T> {
     internal: PropertyInternal<T>,
+    /// The number of ValueRef borrowing the value
+    borrows: Cell<usize>,
     value: core::cell::UnsafeCell<T>,
 }
 
-impl<T: Clone> Property<T> {
+/// A borrow of the value of a Property, returned by `Property::borrow`.
+/// The property cannot change while it exists: setting it, or re-evaluating its binding,
+/// panics.
+pub struct ValueRef<'a, T> {
+    value: &'a T,
+    borrows: &'a Cell<usize>,
+}
+
+impl<'a, T> core::ops::Deref for ValueRef<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
+
+impl<'a, T> Drop for ValueRef<'a, T> {
+    fn drop(&mut self) {
+        self.borrows.set(self.borrows.get() - 1);
+    }
+}
+
+impl<T> Property<T> {
     pub fn set(self: Pin<&Self>, t: T) {
+        trace_event!(property = ?(&self.internal as *const _), "set");
+        unsafe { self.internal.remove_binding() };
+        self.write(t);
+        self.notify_dependencies(true);
+        emit_pending_events();
+    }
+
+    /// Same as set, but the dependent properties and ChangeEvent are not notified if the
+    /// value is equal to the current one. (The binding is removed in any case.)
+    pub fn set_if_changed(self: Pin<&Self>, t: T)
+    where
+        T: PartialEq,
+    {
+        // The current value needs to be up to date to be compared
+        self.evaluate_if_dirty();
+        unsafe { self.internal.remove_binding() };
+        if unsafe { &*self.value.get() } != &t {
+            self.set(t);
+        }
+    }
+
+    /// Remove the binding. The property keeps the last value it produced, without
+    /// re-evaluating it, even if its dependencies changed since.
+    pub fn remove_binding(self: Pin<&Self>) {
         unsafe { self.internal.remove_binding() };
-        unsafe { *self.value.get() = t }
-        self.update_dependencies();
     }
+
+    /// Set a binding. The binding is not evaluated before the property is accessed.
     pub fn set_binding<'a>(self: Pin<&'a Self>, b: Pin<&'a BindingStorage<dyn Binding<T> + 'a>>) {
+        trace_event!(
+            property = ?(&self.internal as *const _),
+            description = %b.binding.description(),
+            "set_binding"
+        );
         unsafe { self.internal.set_binding(b) };
-        self.notify(self);
+        self.mark_dirty();
+        emit_pending_events();
+    }
+
+    /// Re-evaluate the binding as soon as one of its dependencies changes, once the pending
+    /// notifications are done, instead of the next time the property is accessed. This is for
+    /// the bindings with side effects. It is evaluated now if it is dirty.
+    /// Does nothing without binding, and setting another binding resets it.
+    pub fn set_eager(self: Pin<&Self>, eager: bool) {
+        if let Some(b) = unsafe { self.internal.binding() } {
+            b.eager.set(eager);
+            if eager {
+                self.evaluate_if_dirty();
+                emit_pending_events();
+            }
+        }
     }
 
     pub fn set_binding_owned<'a, B: Binding<T> + 'a>(self: Pin<&Self>, b: B) {
+        trace_event!(
+            property = ?(&self.internal as *const _),
+            description = %b.description(),
+            "set_binding"
+        );
         unsafe {
             self.internal
                 .set_binding_box(Box::new(BindingStorage::new(b)))
         };
-        self.notify(self);
+        self.mark_dirty();
+        emit_pending_events();
     }
+}
 
+impl<T: Clone> Property<T> {
+    /// Get the value, re-evaluating the binding first if one of its dependencies has changed.
     pub fn get(self: Pin<&Self>) -> T {
-        self.accessed();
-        unsafe { &*self.value.get() }.clone()
+        self.with(T::clone)
     }
 }
 
 impl<T> Property<T> {
-    fn update_dependencies(self: Pin<&Self>) {
-        let mut v = Default::default();
-        unsafe { &mut *self.internal.notify_dep().as_ptr() }.swap(&mut v);
-        for d in v {
-            let elem = d.elem.clone();
-            core::mem::drop(d); // One need to drop it to remove it from the rev list before calling update.
-            unsafe {
-                Pin::new_unchecked(elem.as_ref()).notify(self);
-            }
+    /// Borrow the value, re-evaluating the binding first if one of its dependencies has changed.
+    /// Like `get`, accessing it from a binding makes it a dependency of that binding.
+    pub fn borrow(self: Pin<&Self>) -> ValueRef<'_, T> {
+        self.evaluate_if_dirty();
+        self.accessed();
+        let this = self.get_ref();
+        this.borrows.set(this.borrows.get() + 1);
+        ValueRef {
+            value: unsafe { &*this.value.get() },
+            borrows: &this.borrows,
         }
     }
-}
 
-impl<T> NotificationReciever for Property<T> {
-    fn notify(self: Pin<&Self>, _from: Pin<&dyn PropertyBase>) {
+    /// Call `f` with a reference to the value. Same as `borrow`, for the duration of `f`.
+    pub fn with<R>(self: Pin<&Self>, f: impl FnOnce(&T) -> R) -> R {
+        f(&self.borrow())
+    }
+
+    /// A property with this value, and no binding
+    pub fn new(value: T) -> Self {
+        Property {
+            internal: PropertyInternal {
+                value: Cell::new(0),
+                phantom: PhantomData,
+            },
+            borrows: Cell::new(0),
+            value: core::cell::UnsafeCell::new(value),
+        }
+    }
+
+    fn write(&self, t: T) {
+        assert!(
+            self.borrows.get() == 0,
+            "Property modified while its value is borrowed"
+        );
+        unsafe { *self.value.get() = t }
+    }
+
+    fn evaluate_if_dirty(self: Pin<&Self>) {
         if let Some(b) = unsafe { self.internal.binding() } {
-            /*if self.updating.get() {
-                panic!("Circular dependency found : {}", self.description());
+            if b.evaluating.get() {
+                // Keep the current value if the policy does not panic
+                binding_loop_detected(&*self);
+                return;
+            }
+            if b.dirty.get() == DirtyState::Clean {
+                return;
             }
-            self.updating.set(true);*/
+
+            struct Guard<'a>(&'a Cell<bool>, *const EvaluationFrame);
+            impl<'a> Drop for Guard<'a> {
+                fn drop(&mut self) {
+                    self.0.set(false);
+                    with_state(|s| s.evaluation_stack.set(self.1));
+                }
+            }
+            let frame = EvaluationFrame {
+                property: unsafe {
+                    // Only stays in the stack for the duration of this function
+                    core::mem::transmute::<&dyn PropertyBase, &(dyn PropertyBase + 'static)>(&*self)
+                },
+                parent: with_state(|s| s.evaluation_stack.get()),
+            };
+            with_state(|s| s.evaluation_stack.set(&frame));
+            b.evaluating.set(true);
+            let _guard = Guard(&b.evaluating, frame.parent);
+
+            if b.dirty.get() == DirtyState::MaybeDirty {
+                // Bring our dependencies up to date, the ones that change will mark us as Dirty.
+                for node in unsafe { &mut *b.rev_dep.as_ptr() }.iter() {
+                    unsafe { Pin::new_unchecked(node.as_ref().source.as_ref()) }.update();
+                    if b.dirty.get() == DirtyState::Dirty {
+                        break;
+                    }
+                }
+                if b.dirty.get() == DirtyState::MaybeDirty {
+                    b.dirty.set(DirtyState::Clean);
+                }
+            }
+            if b.dirty.get() != DirtyState::Dirty {
+                return;
+            }
+            // Reset the flag before the call, so that a dependency changing while we evaluate
+            // marks us dirty again.
+            b.dirty.set(DirtyState::Clean);
             // clear dependency
             unsafe { &mut *b.rev_dep.as_ptr() }.clear();
 
-            let val = run_with_current(self, || b.as_ref().call());
-            // FIXME: check that the property does actualy change
-            unsafe { *self.value.get() = val }
-            self.update_dependencies();
-            //self.updating.set(false);
+            #[cfg(feature = "tracing")]
+            let _span = tracing::trace_span!(
+                "binding",
+                property = ?(&self.internal as *const _),
+                description = %b.as_ref().description(),
+            )
+            .entered();
+            #[cfg(all(feature = "tracing", feature = "std"))]
+            let start = std::time::Instant::now();
+
+            let val = match run_with_current(self, || b.as_ref().try_call()) {
+                Some(val) => val,
+                None => return,
+            };
+            let changed = b.as_ref().has_changed(unsafe { &*self.value.get() }, &val);
+            #[cfg(all(feature = "tracing", feature = "std"))]
+            tracing::trace!(
+                elapsed_us = start.elapsed().as_micros() as u64,
+                changed,
+                "evaluated"
+            );
+            #[cfg(all(feature = "tracing", not(feature = "std")))]
+            tracing::trace!(changed, "evaluated");
+            self.write(val);
+            if changed {
+                self.notify_dependencies(true);
+            }
         }
     }
+
+    /// Mark the binding as dirty and notify all the dependent properties.
+    fn mark_dirty(self: Pin<&Self>) {
+        if let Some(b) = unsafe { self.internal.binding() } {
+            b.dirty.set(DirtyState::Dirty);
+            self.notify_dependencies(true);
+        }
+    }
+
+    fn notify_dependencies(self: Pin<&Self>, dirty: bool) {
+        // The notifications do not run any binding, so the list is not modified while iterating.
+        for node in unsafe { &mut *self.internal.notify_dep().as_ptr() }.iter() {
+            unsafe { Pin::new_unchecked(node.as_ref().elem.as_ref()).notify(node, dirty) }
+        }
+    }
+}
+
+impl<T> NotificationReciever for Property<T> {
+    fn notify(self: Pin<&Self>, node: NonNull<DependencyNode>, dirty: bool) {
+        // Only mark the binding dirty, it will be re-evaluated when the property is accessed,
+        // or from emit_pending_events if it is eager.
+        if let Some(b) = unsafe { self.internal.binding() } {
+            trace_event!(property = ?(&self.internal as *const _), dirty, "notify");
+            let old = b.dirty.get();
+            let state = if dirty {
+                DirtyState::Dirty
+            } else {
+                DirtyState::MaybeDirty
+            };
+            if old < state {
+                b.dirty.set(state);
+            }
+            // If it was not clean, the properties depending on it were already notified.
+            if old == DirtyState::Clean {
+                self.notify_dependencies(false);
+            }
+            // Queued like a ChangeEvent, see `emit`
+            let n = unsafe { node.as_ref() };
+            if b.eager.get() && !n.pending_list.is_linked() {
+                with_state(|s| unsafe { s.pending_events.borrow_mut().0.append(node) });
+            }
+        }
+    }
+
+    fn emit(self: Pin<&Self>, _node: NonNull<DependencyNode>) {
+        // Only queued for the eager bindings. The evaluation may free the node.
+        self.evaluate_if_dirty();
+    }
     fn add_rev_dependency(self: Pin<&Self>, link: NonNull<DependencyNode>) {
         unsafe {
             self.internal
@@ -375,6 +1036,9 @@ impl<T> NotificationReciever for Property<T> {
                 .map(|b| (&mut *b.rev_dep.as_ptr()).append(link));
         }
     }
+    fn as_property(&self) -> Option<&dyn PropertyBase> {
+        Some(self)
+    }
 }
 
 impl<T> PropertyBase for Property<T> {
@@ -383,10 +1047,44 @@ impl<T> PropertyBase for Property<T> {
             (&mut *self.internal.notify_dep().as_ptr()).append(link);
         }
     }
+
+    fn first_dependency(&self) -> Option<NonNull<DependencyNode>> {
+        unsafe { &mut *self.internal.notify_dep().as_ptr() }
+            .iter()
+            .next()
+    }
+
+    fn update(self: Pin<&Self>) {
+        self.evaluate_if_dirty()
+    }
+
+    fn description(&self) -> String {
+        unsafe { self.internal.binding() }
+            .map(|b| b.as_ref().description())
+            .unwrap_or_default()
+    }
+
+    fn has_binding(&self) -> bool {
+        unsafe { self.internal.binding() }.is_some()
+    }
+
+    fn visit_dependencies(&self, f: &mut dyn FnMut(NonNull<dyn PropertyBase>)) {
+        if let Some(b) = unsafe { self.internal.binding() } {
+            for node in unsafe { &mut *b.rev_dep.as_ptr() }.iter() {
+                f(unsafe { node.as_ref() }.source)
+            }
+        }
+    }
+
+    fn visit_dependents(&self, f: &mut dyn FnMut(NonNull<dyn NotificationReciever>)) {
+        for node in unsafe { &mut *self.internal.notify_dep().as_ptr() }.iter() {
+            f(unsafe { node.as_ref() }.elem)
+        }
+    }
 }
 
 pub struct ChangeEvent<F: Fn() + ?Sized> {
-    list: Cell<double_link::Head<NotifyList>>,
+    list: Cell<double_link::Head<SenderList>>,
     func: F,
 }
 
@@ -399,6 +1097,8 @@ impl<F: Fn()> ChangeEvent<F> {
     }
 
     pub fn listen<T>(self: Pin<&Self>, p: Pin<&Property<T>>) {
+        // Only the changes happening after this call are reported
+        p.evaluate_if_dirty();
         self.listen_impl(p)
     }
 
@@ -409,18 +1109,44 @@ impl<F: Fn()> ChangeEvent<F> {
                 &*self,
             )
         };
-        let b = Box::new(DependencyNode::new(s.into()));
-        let b = unsafe { NonNull::new_unchecked(Box::into_raw(b)) };
+        let source = unsafe {
+            core::mem::transmute::<&dyn PropertyBase, &(dyn PropertyBase + 'static)>(&*p)
+        };
+        let b = alloc_node(source.into(), s.into());
         unsafe { (*self.list.as_ptr()).append(b) };
         p.as_ref().add_dependency(b);
     }
 }
 
 impl<F: Fn()> NotificationReciever for ChangeEvent<F> {
-    fn notify(self: Pin<&Self>, from: Pin<&dyn PropertyBase>) {
-        (self.func)();
-        // re-add the signal
-        self.listen_impl(from)
+    fn notify(self: Pin<&Self>, node: NonNull<DependencyNode>, dirty: bool) {
+        // queue the call to the callback until all the bindings are marked dirty.
+        let n = unsafe { node.as_ref() };
+        if dirty {
+            n.pending_dirty.set(true);
+        }
+        if !n.pending_list.is_linked() {
+            with_state(|s| unsafe { s.pending_events.borrow_mut().0.append(node) });
+        }
+    }
+
+    fn emit(self: Pin<&Self>, node: NonNull<DependencyNode>) {
+        let n = unsafe { &mut *node.as_ptr() };
+        // If the property only might have changed, evaluating it tells us if it did.
+        unsafe { Pin::new_unchecked(n.source.as_ref()) }.update();
+        // which may have queued the node again
+        n.pending_list.unlink();
+        if n.pending_dirty.replace(false) {
+            #[cfg(feature = "tracing")]
+            let _span = tracing::trace_span!(
+                "change_event",
+                event = ?(&*self as *const Self as *const ()),
+                source = ?(n.source.cast::<()>()),
+                description = %unsafe { n.source.as_ref() }.description(),
+            )
+            .entered();
+            (self.func)();
+        }
     }
 
     fn add_rev_dependency(self: Pin<&Self>, _link: NonNull<DependencyNode>) {
@@ -428,7 +1154,7 @@ impl<F: Fn()> NotificationReciever for ChangeEvent<F> {
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod t {
 
     use super::*;
@@ -504,6 +1230,71 @@ mod t {
         assert_eq!(i.project_ref().area.get(), 8. + 4.);
     }
 
+    #[cfg(feature = "derive")]
+    #[test]
+    fn test_derive() {
+        #[derive(Properties, Default)]
+        struct Item {
+            pub width: Property<f32>,
+            pub height: Property<f32>,
+            pub area: Property<f32>,
+            pub name: &'static str,
+        }
+
+        let i = Item {
+            name: "item",
+            ..Default::default()
+        };
+        pin_utils::pin_mut!(i);
+        let i = i.as_ref();
+        i.set_height(12.);
+        i.set_width(8.);
+        binding!(area = move || i.get_height() * i.get_width());
+        i.set_area_binding(area);
+        assert_eq!(i.get_area(), 12. * 8.);
+        i.width().set(4.);
+        assert_eq!(i.area().get(), 12. * 4.);
+        assert_eq!(i.name, "item");
+    }
+
+    #[cfg(feature = "derive")]
+    #[test]
+    fn test_derive_generics() {
+        #[derive(Default, Debug, PartialEq)]
+        struct NotClone(u32);
+
+        #[derive(Properties, Default)]
+        struct Generic<'a, T: Default> {
+            value: Property<T>,
+            count: Property<u32>,
+            name: &'a str,
+        }
+
+        #[derive(Properties, Default)]
+        struct Holder {
+            value: Property<NotClone>,
+        }
+
+        let name = String::from("generic");
+        let g = Generic::<NotClone> {
+            name: &name,
+            ..Default::default()
+        };
+        pin_utils::pin_mut!(g);
+        let g = g.as_ref();
+        g.set_count(3);
+        binding!(value = move || NotClone(g.get_count() * 2));
+        g.set_value_binding(value);
+        assert_eq!(*g.value().borrow(), NotClone(6));
+        assert_eq!(g.name, "generic");
+
+        let h = Holder::default();
+        pin_utils::pin_mut!(h);
+        let h = h.as_ref();
+        h.set_value(NotClone(4));
+        assert_eq!(h.value().with(|v| v.0), 4);
+    }
+
     #[test]
     fn test_notify() {
         let x = Cell::new(0);
@@ -527,4 +1318,356 @@ mod t {
         bar.set(8);
         assert_eq!(x.get(), 4);
     }
+
+    #[test]
+    fn test_diamond() {
+        #[pin_project::pin_project]
+        #[derive(Default)]
+        struct Item {
+            #[pin]
+            pub a: Property<i32>,
+            #[pin]
+            pub b: Property<i32>,
+            #[pin]
+            pub c: Property<i32>,
+            #[pin]
+            pub d: Property<i32>,
+        }
+
+        let i = Item::default();
+        pin_utils::pin_mut!(i);
+        let i = i.as_ref();
+        let count = Cell::new(0);
+        let seen = RefCell::new(Vec::new());
+        i.project_ref().a.set(1);
+        i.project_ref()
+            .b
+            .set_binding_owned(|| i.project_ref().a.get() + 1);
+        i.project_ref()
+            .c
+            .set_binding_owned(|| i.project_ref().a.get() * 2);
+        i.project_ref().d.set_binding_owned(|| {
+            count.set(count.get() + 1);
+            i.project_ref().b.get() * 100 + i.project_ref().c.get()
+        });
+        let e = ChangeEvent::new(|| seen.borrow_mut().push(i.project_ref().d.get()));
+        pin_utils::pin_mut!(e);
+        e.as_ref().listen(i.project_ref().d);
+        assert_eq!(i.project_ref().d.get(), 2 * 100 + 2);
+        assert_eq!(count.get(), 1);
+
+        i.project_ref().a.set(2);
+        assert_eq!(count.get(), 2);
+        assert_eq!(*seen.borrow(), vec![3 * 100 + 4]);
+        i.project_ref().a.set(5);
+        assert_eq!(count.get(), 3);
+        assert_eq!(*seen.borrow(), vec![3 * 100 + 4, 6 * 100 + 10]);
+
+        // A ChangeEvent on both sides of the diamond sees the new values on both
+        let seen2 = RefCell::new(Vec::new());
+        let e2 = ChangeEvent::new(|| {
+            seen2
+                .borrow_mut()
+                .push((i.project_ref().b.get(), i.project_ref().c.get()))
+        });
+        pin_utils::pin_mut!(e2);
+        e2.as_ref().listen(i.project_ref().b);
+        e2.as_ref().listen(i.project_ref().c);
+        i.project_ref().a.set(1);
+        assert_eq!(*seen2.borrow(), vec![(2, 2), (2, 2)]);
+        assert_eq!(count.get(), 4);
+    }
+
+    #[test]
+    fn test_if_changed() {
+        let count = Cell::new(0);
+        let notified = Cell::new(0);
+        let a = Property::default();
+        let b = Property::default();
+        let c = Property::default();
+        pin_utils::pin_mut!(a);
+        pin_utils::pin_mut!(b);
+        pin_utils::pin_mut!(c);
+        let (a, b, c) = (a.as_ref(), b.as_ref(), c.as_ref());
+        a.set(11);
+        b.set_binding_owned(IfChanged(|| a.get() / 10));
+        c.set_binding_owned(|| {
+            count.set(count.get() + 1);
+            b.get() * 2
+        });
+        let e = ChangeEvent::new(|| notified.set(notified.get() + 1));
+        pin_utils::pin_mut!(e);
+        e.as_ref().listen(b);
+        assert_eq!(c.get(), 2);
+        assert_eq!(count.get(), 1);
+        a.set(12);
+        assert_eq!(c.get(), 2);
+        assert_eq!(count.get(), 1);
+        assert_eq!(notified.get(), 0);
+        a.set(25);
+        assert_eq!(notified.get(), 1);
+        assert_eq!(c.get(), 4);
+        assert_eq!(count.get(), 2);
+
+        let e2 = ChangeEvent::new(|| notified.set(notified.get() + 1));
+        pin_utils::pin_mut!(e2);
+        e2.as_ref().listen(a);
+        a.set_if_changed(25);
+        assert_eq!(notified.get(), 1);
+        a.set_if_changed(26);
+        assert_eq!(notified.get(), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "Binding loop detected")]
+    fn test_binding_loop() {
+        let a = Property::<i32>::default();
+        let b = Property::default();
+        pin_utils::pin_mut!(a);
+        pin_utils::pin_mut!(b);
+        let (a, b) = (a.as_ref(), b.as_ref());
+        a.set_binding_owned(|| b.get() + 1);
+        b.set_binding_owned(|| a.get() + 1);
+        a.get();
+    }
+
+    #[test]
+    fn test_borrow() {
+        // Not Clone
+        #[derive(Default)]
+        struct Names(Vec<String>);
+        let names = Property::<Names>::default();
+        let count = Property::default();
+        pin_utils::pin_mut!(names);
+        pin_utils::pin_mut!(count);
+        let (names, count) = (names.as_ref(), count.as_ref());
+        count.set_binding_owned(|| names.with(|n| n.0.len()));
+        assert_eq!(count.get(), 0);
+        names.set(Names(vec!["a".into(), "b".into()]));
+        assert_eq!(count.get(), 2);
+        {
+            let n = names.borrow();
+            let n2 = names.borrow();
+            assert_eq!(n.0[1], n2.0[1]);
+            assert_eq!(count.get(), 2);
+        }
+        names.set(Names(vec![]));
+        assert_eq!(count.get(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Property modified while its value is borrowed")]
+    fn test_set_while_borrowed() {
+        let a = Property::new(String::from("a"));
+        pin_utils::pin_mut!(a);
+        let a = a.as_ref();
+        a.with(|_| a.set("b".into()));
+    }
+
+    #[test]
+    fn test_eager() {
+        let evaluations = Cell::new(0);
+        let x = Property::new(1);
+        let double = Property::default();
+        pin_utils::pin_mut!(x);
+        pin_utils::pin_mut!(double);
+        let (x, double) = (x.as_ref(), double.as_ref());
+        double.set_binding_owned(|| {
+            evaluations.set(evaluations.get() + 1);
+            x.get() * 2
+        });
+        double.set_eager(true);
+        assert_eq!(evaluations.get(), 1);
+        // Evaluated once per change, without being read
+        batch(|| {
+            x.set(2);
+            x.set(3);
+        });
+        assert_eq!(evaluations.get(), 2);
+        assert_eq!(double.get(), 6);
+        assert_eq!(evaluations.get(), 2);
+        double.set_eager(false);
+        x.set(4);
+        assert_eq!(evaluations.get(), 2);
+    }
+
+    #[test]
+    fn test_batch() {
+        let count = Cell::new(0);
+        let x = Property::default();
+        let y = Property::default();
+        let sum = Property::default();
+        pin_utils::pin_mut!(x);
+        pin_utils::pin_mut!(y);
+        pin_utils::pin_mut!(sum);
+        let (x, y, sum) = (x.as_ref(), y.as_ref(), sum.as_ref());
+        sum.set_binding_owned(|| x.get() + y.get());
+        let e = ChangeEvent::new(|| count.set(count.get() + 1));
+        pin_utils::pin_mut!(e);
+        e.as_ref().listen(sum);
+        e.as_ref().listen(x);
+
+        batch(|| {
+            x.set(1);
+            y.set(2);
+            batch(|| y.set(3));
+            assert_eq!(count.get(), 0);
+            assert_eq!(sum.get(), 4);
+        });
+        assert_eq!(count.get(), 2);
+
+        let r = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            batch(|| {
+                x.set(10);
+                panic!("oops");
+            })
+        }));
+        assert!(r.is_err());
+        assert_eq!(count.get(), 2);
+        assert_eq!(sum.get(), 13);
+        y.set(5);
+        assert_eq!(count.get(), 4);
+        assert_eq!(sum.get(), 15);
+    }
+
+    #[test]
+    fn test_lazy() {
+        let count = Cell::new(0);
+        let a = Property::default();
+        let b = Property::default();
+        let c = Property::default();
+        pin_utils::pin_mut!(a);
+        pin_utils::pin_mut!(b);
+        pin_utils::pin_mut!(c);
+        let (a, b, c) = (a.as_ref(), b.as_ref(), c.as_ref());
+        a.set(1);
+        b.set_binding_owned(|| {
+            count.set(count.get() + 1);
+            a.get() * 2
+        });
+        c.set_binding_owned(|| {
+            count.set(count.get() + 1);
+            b.get() + 1
+        });
+        assert_eq!(count.get(), 0);
+        assert_eq!(c.get(), 3);
+        assert_eq!(count.get(), 2);
+        a.set(2);
+        a.set(3);
+        a.set(4);
+        assert_eq!(count.get(), 2);
+        assert_eq!(b.get(), 8);
+        assert_eq!(count.get(), 3);
+        assert_eq!(c.get(), 9);
+        assert_eq!(c.get(), 9);
+        assert_eq!(count.get(), 4);
+    }
+
+    #[test]
+    fn test_dependency_nodes() {
+        fn count_dependencies<T>(p: Pin<&Property<T>>) -> usize {
+            unsafe { &mut *p.internal.notify_dep().as_ptr() }
+                .iter()
+                .count()
+        }
+        let a = Property::default();
+        let b = Property::default();
+        let c = Property::default();
+        pin_utils::pin_mut!(a);
+        pin_utils::pin_mut!(b);
+        pin_utils::pin_mut!(c);
+        let (a, b, c) = (a.as_ref(), b.as_ref(), c.as_ref());
+        a.set(2);
+        b.set(3);
+        c.set_binding_owned(|| a.get() * a.get() + b.get() * a.get());
+        assert_eq!(c.get(), 10);
+        assert_eq!(count_dependencies(a), 1);
+        assert_eq!(count_dependencies(b), 1);
+
+        // The freed nodes are reused
+        let pool_size = || with_state(|s| s.node_pool.borrow().len());
+        a.set(3);
+        let before = pool_size();
+        assert_eq!(c.get(), 18);
+        assert_eq!(pool_size(), before);
+        assert_eq!(count_dependencies(a), 1);
+
+        set_node_pool_capacity(0);
+        assert_eq!(pool_size(), 0);
+        a.set(4);
+        assert_eq!(c.get(), 28);
+        assert_eq!(pool_size(), 0);
+        set_node_pool_capacity(DEFAULT_NODE_POOL_CAPACITY);
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn test_tracing() {
+        use tracing::span::{Attributes, Id, Record};
+        use tracing::{Event, Metadata};
+
+        /// Record the name of the spans and the message of the events
+        #[derive(Default)]
+        struct Recorder(std::sync::Mutex<Vec<String>>);
+        impl tracing::Subscriber for Recorder {
+            fn enabled(&self, _: &Metadata) -> bool {
+                true
+            }
+            fn new_span(&self, span: &Attributes) -> Id {
+                self.0.lock().unwrap().push(span.metadata().name().into());
+                Id::from_u64(1)
+            }
+            fn record(&self, _: &Id, _: &Record) {}
+            fn record_follows_from(&self, _: &Id, _: &Id) {}
+            fn event(&self, event: &Event) {
+                struct Message<'a>(&'a mut Vec<String>);
+                impl<'a> tracing::field::Visit for Message<'a> {
+                    fn record_debug(
+                        &mut self,
+                        field: &tracing::field::Field,
+                        value: &dyn core::fmt::Debug,
+                    ) {
+                        if field.name() == "message" {
+                            self.0.push(format!("{:?}", value));
+                        }
+                    }
+                }
+                event.record(&mut Message(&mut self.0.lock().unwrap()));
+            }
+            fn enter(&self, _: &Id) {}
+            fn exit(&self, _: &Id) {}
+        }
+
+        let recorder = std::sync::Arc::new(Recorder::default());
+        tracing::subscriber::with_default(recorder.clone(), || {
+            let a = Property::default();
+            let b = Property::default();
+            let e = ChangeEvent::new(|| {});
+            pin_utils::pin_mut!(a);
+            pin_utils::pin_mut!(b);
+            pin_utils::pin_mut!(e);
+            let (a, b) = (a.as_ref(), b.as_ref());
+            a.set(1);
+            b.set_binding_owned(|| a.get() + 1);
+            e.as_ref().listen(b);
+            a.set(2);
+        });
+        let log = recorder.0.lock().unwrap().clone();
+        assert_eq!(
+            log,
+            [
+                "set",
+                "set_binding",
+                "binding",
+                "evaluated",
+                "set",
+                "notify",
+                "binding",
+                "evaluated",
+                "change_event",
+                // when b is dropped
+                "remove_binding"
+            ]
+        );
+    }
 }
@@ -1,26 +1,40 @@
-use super::items::{Item, MouseEvent};
+use super::items::{Item, MouseButton, MouseEvent};
 use qmetaobject::scenegraph::{ContainerNode, SGNode};
 use qmetaobject::{QObject, QQuickItem, QRectF};
 use std::any::TypeId;
 use std::collections::hash_map::DefaultHasher;
 use std::ffi::CString;
 use std::hash::{Hash, Hasher};
+use std::pin::Pin;
 use std::rc::Rc;
 
+cpp! {{
+    #include <QtGui/QGuiApplication>
+}}
+
+/// The Qt::MouseButtons which are down
+fn mouse_buttons() -> u32 {
+    cpp!(unsafe [] -> u32 as "uint" {
+        return QGuiApplication::mouseButtons();
+    })
+}
+
 /// Use as a factory for RSMLItem
 pub trait ItemFactory {
-    fn create() -> Rc<dyn Item<'static>>;
+    fn create() -> Pin<Rc<dyn Item<'static>>>;
 }
 
 /// A QQuickItem which is showing an Item
 #[derive(QObject)]
 pub struct RSMLItem<T: ItemFactory + 'static> {
     base: qt_base_class!(trait QQuickItem),
-    node: Option<Rc<dyn Item<'static> + 'static>>,
+    node: Option<Pin<Rc<dyn Item<'static> + 'static>>>,
+    /// The Qt::MouseButtons which were down at the previous mouse event
+    buttons: u32,
     _phantom: ::std::marker::PhantomData<T>,
 }
 impl<T: ItemFactory + 'static> RSMLItem<T> {
-    fn set_node(&mut self, node: Rc<dyn Item<'static>>) {
+    fn set_node(&mut self, node: Pin<Rc<dyn Item<'static>>>) {
         node.init(self);
         self.node = Some(node);
         {
@@ -29,7 +43,7 @@ impl<T: ItemFactory + 'static> RSMLItem<T> {
             assert!(!obj.is_null());
             cpp!(unsafe [obj as "QQuickItem*"] {
                 obj->setFlag(QQuickItem::ItemHasContents);
-                obj->setAcceptedMouseButtons(Qt::LeftButton);
+                obj->setAcceptedMouseButtons(Qt::AllButtons);
             });
         }
         (self as &dyn QQuickItem).update();
@@ -40,6 +54,7 @@ impl<T: ItemFactory + 'static> Default for RSMLItem<T> {
         RSMLItem {
             base: Default::default(),
             node: None,
+            buttons: 0,
             _phantom: Default::default(),
         }
     }
@@ -55,8 +70,10 @@ impl<T: ItemFactory + 'static> QQuickItem for RSMLItem<T> {
 
     fn geometry_changed(&mut self, new_geometry: QRectF, _old_geometry: QRectF) {
         if let Some(ref i) = self.node {
-            i.geometry().width.set(new_geometry.width);
-            i.geometry().height.set(new_geometry.height);
+            crate::properties::batch(|| {
+                i.geometry().width().set(new_geometry.width);
+                i.geometry().height().set(new_geometry.height);
+            });
         }
         (self as &dyn QQuickItem).update();
     }
@@ -67,9 +84,14 @@ impl<T: ItemFactory + 'static> QQuickItem for RSMLItem<T> {
 
     fn mouse_event(&mut self, event: ::qmetaobject::QMouseEvent<'_>) -> bool {
         let pos = event.position();
+        // QMouseEvent does not expose the button: it is the one which changed since the
+        // previous event
+        let buttons = mouse_buttons();
+        let changed = MouseButton::from_qt(buttons ^ self.buttons);
+        self.buttons = buttons;
         let e = match event.event_type() {
-            ::qmetaobject::QMouseEventType::MouseButtonPress => MouseEvent::Press(pos),
-            ::qmetaobject::QMouseEventType::MouseButtonRelease => MouseEvent::Release(pos),
+            ::qmetaobject::QMouseEventType::MouseButtonPress => MouseEvent::Press(pos, changed),
+            ::qmetaobject::QMouseEventType::MouseButtonRelease => MouseEvent::Release(pos, changed),
             ::qmetaobject::QMouseEventType::MouseMove => MouseEvent::Move(pos),
         };
         self.node.as_ref().map_or(false, |n| n.mouse_event(e))
@@ -87,6 +109,12 @@ pub fn show_window<T: ItemFactory + 'static>() {
     ::qmetaobject::qml_register_type::<RSMLItem<T>>(&name, 1, 0, &name);
     let mut engine = ::qmetaobject::QmlEngine::new();
 
+    // Run the futures of the async bindings from the event loop
+    let run_pending = ::qmetaobject::queued_callback(|()| {
+        crate::executor::run_pending();
+    });
+    crate::executor::set_wakeup(move || run_pending(()));
+
     engine.load_data(
         format!(
             r#"
@@ -12,14 +12,23 @@ macro_rules! rsml {
     };
 
     (@parse_fields $(#[$attrs:meta])*, [$($vis:tt)*], $name:ident $(: $derive:ident)*,
-            $(@signal /*$svis:vis*/ $signal:ident),* $(,)*
+            $(@signal /*$svis:vis*/ $signal:ident $(($($sarg:ident : $sarg_ty:ty),*))* $(-> $sret:ty)? ,)*
+            $(@alias $alias:ident : $alias_ty:ty = $alias_id:ident $(. $alias_field:ident)+ ,)*
+            $(@readonly $readonly:ident : $readonly_ty:ty $(= $readonly_value:expr)* ,)*
             $(/*$fvis:vis*/ $field:ident : $typ:ty  $(= $value:expr )* ),* $(,)*
             $(; $($sub_items:tt)* )*
             ) => {
+        // The lint ignores the attribute on the DeriveItem field itself
+        #[allow(non_snake_case)]
         $(#[$attrs])* $($vis)* struct $name<'a> {
-            $( DeriveItem : ::std::rc::Rc<$derive<'a>> ,)*
-            $( pub $signal : $crate::properties::Signal<'a>, )*
-            $( pub $field : $crate::properties::Property<'a, $typ> ),*
+            $( DeriveItem : ::std::pin::Pin<::std::rc::Rc<$derive<'a>>> ,)*
+            $( $signal : $crate::properties::Signal<'a, ($($($sarg_ty,)*)*) $(, $sret)?>, )*
+            $( $alias : $crate::properties::PropertyRef<'a, $alias_ty>, )*
+            // Only the binding of the declaration, or the code of this module, can set them
+            $( $readonly : $crate::properties::PinnedProperty<'a, $readonly_ty>, )*
+            $( $field : $crate::properties::Property<'a, $typ>, )*
+            // new() is the only way to create it, and it pins it in a Rc
+            _pinned: $crate::properties::AlwaysPinned,
         }
         /*impl<'a> Default for $name<'a> {
             fn default() -> Self {
@@ -29,17 +38,51 @@ macro_rules! rsml {
             }
         }*/
         impl<'a> $name<'a> {
-            pub fn new() -> ::std::rc::Rc<Self> {
+            $(
+                pub fn $signal(&self) -> &$crate::properties::Signal<'a, ($($($sarg_ty,)*)*) $(, $sret)?> {
+                    &self.$signal
+                }
+            )*
+            $(
+                pub fn $alias(&self) -> ::std::pin::Pin<&$crate::properties::Property<'a, $alias_ty>> {
+                    self.$alias.get()
+                }
+            )*
+            $(
+                pub fn $readonly(&self) -> &$crate::properties::ReadOnlyProperty<'a, $readonly_ty> {
+                    self.$readonly.property().as_read_only()
+                }
+            )*
+            $(
+                pub fn $field(&self) -> ::std::pin::Pin<&$crate::properties::Property<'a, $typ>> {
+                    unsafe { ::std::pin::Pin::new_unchecked(&self.$field) }
+                }
+            )*
+
+            pub fn new() -> ::std::pin::Pin<::std::rc::Rc<Self>> {
                 $(#[allow(non_snake_case)] let $derive = rsml!(@init_derive $name $derive { $($sub_items)* }) ;)*
-                let r = ::std::rc::Rc::new(Self {
+                // The children with an @id, for the aliases
+                $(#[allow(unused_variables)] let ids = $derive.1.clone();)*
+                let r = ::std::rc::Rc::pin(Self {
                     $( DeriveItem : $derive.0 ,)*
                     $( $signal: Default::default(), )*
-                    $( $field: rsml!{@parse_default $($value)*} ),* }
-                );
+                    $( $alias: $crate::properties::PropertyRef::new_or_detached(
+                        ids.borrow().$alias_id.upgrade(),
+                        |i| ::std::pin::Pin::get_ref(i) $(. $alias_field())+), )*
+                    $( $readonly: unsafe {
+                        $crate::properties::PinnedProperty::new(rsml!{@parse_default $($readonly_value)*})
+                    }, )*
+                    $( $field: rsml!{@parse_default $($value)*}, )*
+                    _pinned: unsafe { $crate::properties::AlwaysPinned::new() },
+                });
+                // So that dropping it from a WeakProperty access is deferred
+                $( r.$readonly.property().set_owner(&r); )*
+                $( r.$field().set_owner(&r); )*
                 $(
-                    $derive.1.borrow_mut().$name = ::std::rc::Rc::downgrade(&r);
+                    $derive.1.borrow_mut().$name = $crate::properties::PinWeak::downgrade(r.clone());
                     ($derive.2)();
                 )*
+                $(rsml!{ @init_readonly r, $name, $readonly, $($readonly_value)* })*
                 $(rsml!{ @init_field r, $name, $field, $($value)* })*
                 r
             }
@@ -57,16 +100,28 @@ macro_rules! rsml {
     //(@init_field $r:ident, $field:ident, = |$s:ident| $bind:expr) => {}
     (@init_field $r:ident, $name:ident, $field:ident $(. $field_cont:ident)* , $bind:expr) => {
         {
-            let wr = ::std::rc::Rc::downgrade(&$r);
+            let wr = $crate::properties::PinWeak::downgrade($r.clone());
             #[allow(unused_variables)]
             #[allow(non_snake_case)]
-            $r.$field $(. $field_cont)* .set_binding(move || { let $name = wr.upgrade().unwrap(); $bind });
-            /*$r.$field $(. $field_cont)* .set_binding((stringify!($name::$field $(. $field_cont)*).to_owned(),
-                move || Some({ let $name = wr.upgrade()?; $bind })));*/
+            $r.$field() $(. $field_cont())* .set_binding((
+                concat!(stringify!($name), "::", stringify!($field) $(, ".", stringify!($field_cont))*).to_owned(),
+                // The binding fails, instead of panicking, once the item is gone
+                move || { let $name = wr.upgrade()?; Some($bind) }));
         }
     };
     (@init_field $r:ident, $name:ident, $field:ident $(. $field_cont:ident)* ,) => { };
     //(@init_field $r:ident, $field:ident, = $vale:expr) => { };
+    (@init_readonly $r:ident, $name:ident, $field:ident, $bind:expr) => {
+        {
+            let wr = $crate::properties::PinWeak::downgrade($r.clone());
+            #[allow(unused_variables)]
+            #[allow(non_snake_case)]
+            $r.$field.property().set_binding((
+                concat!(stringify!($name), "::", stringify!($field)).to_owned(),
+                move || { let $name = wr.upgrade()?; Some($bind) }));
+        }
+    };
+    (@init_readonly $r:ident, $name:ident, $field:ident,) => { };
 
     //(@parse_default = || $bind:expr) => { Property::from_binding(||$bind) };
     //(@parse_default = $value:expr) => { Property::from($value) };
@@ -80,8 +135,8 @@ macro_rules! rsml {
         #[derive(Default)]
         #[allow(non_snake_case)]
         struct IdsContainer<'a> {
-            $($ids: ::std::rc::Weak<$ids_ty<'a>> ,)*
-            $parent : ::std::rc::Weak<$parent<'a>> ,
+            $($ids: $crate::properties::PinWeak<$ids_ty<'a>> ,)*
+            $parent : $crate::properties::PinWeak<$parent<'a>> ,
         }
         #[allow(unused_variables)]
         let container = ::std::rc::Rc::new(::std::cell::RefCell::new(IdsContainer::default()));
@@ -100,13 +155,13 @@ macro_rules! rsml {
         #[derive(Default)]
         #[allow(non_snake_case)]
         struct IdsContainer<'a> {
-            $($ids: ::std::rc::Weak<$ids_ty<'a>> ,)*
-            $name : ::std::rc::Weak<$name<'a>> ,
+            $($ids: $crate::properties::PinWeak<$ids_ty<'a>> ,)*
+            $name : $crate::properties::PinWeak<$name<'a>> ,
         }
         #[allow(unused_variables)]
         let container = ::std::rc::Rc::new(::std::cell::RefCell::new(IdsContainer::default()));
         let (r, init) = rsml!{@parse_as_initialize (parse_as_initialize_end { $name container  [$name $($ids)*]}), fields: [], sub_items: [], id: [], $($rest)* };
-        container.borrow_mut().$name = ::std::rc::Rc::downgrade(&r);
+        container.borrow_mut().$name = $crate::properties::PinWeak::downgrade(r.clone());
         init();
         r
     } };
@@ -116,12 +171,30 @@ macro_rules! rsml {
         rsml!{@$callback $callback_data fields: $fields, sub_items: $sub_items, id: $id}
     };
 
+    // Signal handler which names the arguments: `clicked: |pos| ...`
+    (@parse_as_initialize $callback:tt, fields: [$($fields:tt)*], sub_items: $sub_items:tt, id: $id:tt, $field:ident $(. $field_cont:ident)* : |$($arg:ident),*| $value:expr, $($rest:tt)* ) => {
+        rsml!{@parse_as_initialize $callback, fields: [$($fields)* [@handler $field $(. $field_cont)* ($($arg)*) : $value] ],
+            sub_items: $sub_items, id: $id, $($rest)* }
+    };
+    (@parse_as_initialize $callback:tt, fields: [$($fields:tt)*], sub_items: $sub_items:tt, id: $id:tt, $field:ident $(. $field_cont:ident)* : |$($arg:ident),*| $value:expr ) => {
+        rsml!{@parse_as_initialize $callback, fields: [$($fields)* [@handler $field $(. $field_cont)* ($($arg)*) : $value] ],
+            sub_items: $sub_items, id: $id, }
+    };
+    // Two-way binding with another property: `text <=> model.name`
+    (@parse_as_initialize $callback:tt, fields: [$($fields:tt)*], sub_items: $sub_items:tt, id: $id:tt, $field:ident $(. $field_cont:ident)* <=> $value:expr, $($rest:tt)* ) => {
+        rsml!{@parse_as_initialize $callback, fields: [$($fields)* [@bidirectional $field $(. $field_cont)* : $value] ],
+            sub_items: $sub_items, id: $id, $($rest)* }
+    };
+    (@parse_as_initialize $callback:tt, fields: [$($fields:tt)*], sub_items: $sub_items:tt, id: $id:tt, $field:ident $(. $field_cont:ident)* <=> $value:expr ) => {
+        rsml!{@parse_as_initialize $callback, fields: [$($fields)* [@bidirectional $field $(. $field_cont)* : $value] ],
+            sub_items: $sub_items, id: $id, }
+    };
     (@parse_as_initialize $callback:tt, fields: [$($fields:tt)*], sub_items: $sub_items:tt, id: $id:tt, $field:ident $(. $field_cont:ident)* : $value:expr, $($rest:tt)* ) => {
-        rsml!{@parse_as_initialize $callback, fields: [$($fields)* $field $(. $field_cont)* : $value , ],
+        rsml!{@parse_as_initialize $callback, fields: [$($fields)* [$field $(. $field_cont)* : $value] ],
             sub_items: $sub_items, id: $id, $($rest)* }
     };
     (@parse_as_initialize $callback:tt, fields: [$($fields:tt)*], sub_items: $sub_items:tt, id: $id:tt, $field:ident $(. $field_cont:ident)* : $value:expr ) => {
-        rsml!{@parse_as_initialize $callback, fields: [$($fields)* $field $(. $field_cont)* : $value , ],
+        rsml!{@parse_as_initialize $callback, fields: [$($fields)* [$field $(. $field_cont)* : $value] ],
             sub_items: $sub_items, id: $id, }
     };
     (@parse_as_initialize $callback:tt, fields: $fields:tt, sub_items: [$($sub_items:tt)*], id: $id:tt, $nam:ident { $($inner:tt)* }  $($rest:tt)* ) => {
@@ -141,14 +214,14 @@ macro_rules! rsml {
         init
     };
 
-    (@parse_as_initialize_end { $name:ident $container:ident $ids:tt } fields: [$($field:ident $(. $field_cont:ident)* : $value:expr ,)*], sub_items: [$($sub_items:tt)*], id: [$($id:tt)*]) => { {
+    (@parse_as_initialize_end { $name:ident $container:ident $ids:tt } fields: [$($field:tt)*], sub_items: [$($sub_items:tt)*], id: [$($id:tt)*]) => { {
         let r = <$name>::new();
-        $( $container.borrow_mut().$id = ::std::rc::Rc::downgrade(&r); )*
+        $( $container.borrow_mut().$id = $crate::properties::PinWeak::downgrade(r.clone()); )*
         let init = || {};
         $(let i = { rsml!{ @init_sub_items r $container $ids, $sub_items} }; let init = move || { init(); i(); };)*
         #[allow(unused_variables)]
         let container = $container.clone();
-        (r.clone(),  move || {init();  $(rsml!{ @init_field_with_ids r, container, $ids, $field $(. $field_cont)* , $value })* })
+        (r.clone(),  move || {init();  $(rsml!{ @init_field_with_ids r, $name, container, $ids, $field })* })
     } };
 
 
@@ -170,18 +243,43 @@ macro_rules! rsml {
     };
 
 
-    (@init_field_with_ids $r:ident, $container:ident, [$($id:ident)*], $field:ident $(. $field_cont:ident)* , $bind:expr) => {
+    (@init_field_with_ids $r:ident, $name:ident, $container:ident, [$($id:ident)*], [@handler $field:ident $(. $field_cont:ident)* ($($arg:ident)*) : $bind:expr]) => {
         {
             #[allow(unused_variables)]
             let container = $container.clone();
             #[allow(unused_variables)]
             #[allow(non_snake_case)]
-            $r.$field $(. $field_cont)* .set_binding(move || { $(let $id = container.borrow().$id.upgrade().unwrap();)* $bind });
-            /*$r.$field $(. $field_cont)* .set_binding((stringify!($name::$field $(. $field_cont)*).to_owned(),
-                move || Some({ let $name = wr.upgrade()?; $bind })));*/
+            $r.$field() $(. $field_cont())* .set_binding($crate::properties::Fallible(
+                move |($($arg,)*)| {
+                    $(let $id = container.borrow().$id.upgrade()
+                        .ok_or(concat!("the item ", stringify!($id), " was dropped"))?;)*
+                    Ok::<_, &str>($bind)
+                }));
         }
     };
-    (@init_field_with_ids $r:ident, $container:ident, $ids:tt, $field:ident $(. $field_cont:ident)* ,) => { };
+    (@init_field_with_ids $r:ident, $name:ident, $container:ident, [$($id:ident)*], [@bidirectional $field:ident $(. $field_cont:ident)* : $other:expr]) => {
+        // The other property is only looked up once, its current value is the initial one
+        #[allow(unused_variables)]
+        #[allow(non_snake_case)]
+        (|| {
+            // Nothing to bind to if an item is gone
+            $(let $id = $container.borrow().$id.upgrade()?;)*
+            ($other).bind_bidirectional($r.$field() $(. $field_cont())*);
+            Some(())
+        })();
+    };
+    (@init_field_with_ids $r:ident, $name:ident, $container:ident, [$($id:ident)*], [$field:ident $(. $field_cont:ident)* : $bind:expr]) => {
+        {
+            #[allow(unused_variables)]
+            let container = $container.clone();
+            #[allow(unused_variables)]
+            #[allow(non_snake_case)]
+            $r.$field() $(. $field_cont())* .set_binding((
+                concat!(stringify!($name), "::", stringify!($field) $(, ".", stringify!($field_cont))*).to_owned(),
+                move || { $(let $id = container.borrow().$id.upgrade()?;)* Some($bind) }));
+        }
+    };
+    (@init_field_with_ids $r:ident, $name:ident, $container:ident, $ids:tt, [$field:ident $(. $field_cont:ident)* :]) => { };
 
 }
 
@@ -217,12 +315,15 @@ macro_rules! rsml_init {
 
 #[cfg(test)]
 mod tests {
+    use std::cell::RefCell;
+    use std::pin::Pin;
+    use std::rc::Rc;
 
     rsml! {
         struct Rectangle2 {
             width: u32 = 2,
             height: u32,
-            area: u32 = Rectangle2.width.value() * Rectangle2.height.value()
+            area: u32 = Rectangle2.width().value() * Rectangle2.height().value()
         }
     }
 
@@ -230,23 +331,141 @@ mod tests {
     fn test_rsml() {
         let rec = Rectangle2::new(); // Rc::new(RefCell::new(Rectangle2::default()));
                                      //         let wr = Rc::downgrade(&rec);
-                                     //         rec.borrow_mut().area = Property::from_binding(move || wr.upgrade().map(|wr| wr.borrow().width.value() * wr.borrow().height.value()).unwrap());
-        rec.height.set(4);
-        assert_eq!(rec.area.value(), 4 * 2);
-        rec.height.set(8);
-        assert_eq!(rec.area.value(), 8 * 2);
+                                     //         rec.borrow_mut().area = Property::from_binding(move || wr.upgrade().map(|wr| wr.borrow().width().value() * wr.borrow().height().value()).unwrap());
+        rec.height().set(4);
+        assert_eq!(rec.area().value(), 4 * 2);
+        rec.height().set(8);
+        assert_eq!(rec.area().value(), 8 * 2);
     }
 
     #[test]
     fn test_rsml_init() {
         let rec = rsml! {
             Rectangle2 {
-                height: Rectangle2.width.value() * 3,
+                height: Rectangle2.width().value() * 3,
+            }
+        };
+        assert_eq!(rec.area().value(), 3 * 2 * 2);
+        rec.width().set(8);
+        assert_eq!(rec.area().value(), 3 * 8 * 8);
+    }
+
+    rsml! {
+        struct Counter {
+            @signal clicked(x: u32, y: u32),
+            @signal query() -> u32,
+            total: u32,
+        }
+    }
+
+    #[test]
+    fn test_rsml_signal() {
+        let c = rsml! {
+            Counter {
+                clicked: |x, y| Counter.total().set(Counter.total().get() + x * y),
+                query: Counter.total().get() + 1,
+            }
+        };
+        c.clicked().emit((2, 3));
+        c.clicked().emit((1, 4));
+        assert_eq!(c.total().get(), 10);
+        assert_eq!(c.query().emit_with((), 0, |acc, r| acc + r), 11);
+    }
+
+    #[test]
+    fn test_rsml_bidirectional() {
+        let model = Rectangle2::new();
+        model.height().set(5);
+        let model2 = model.clone();
+        let rec = rsml! {
+            Rectangle2 {
+                height <=> model2.height(),
+                width <=> Rectangle2.height(),
+            }
+        };
+        assert_eq!(rec.area().value(), 5 * 5);
+        rec.width().set(3);
+        assert_eq!(model.height().value(), 3);
+        model.height().set(4);
+        assert_eq!(rec.area().value(), 4 * 4);
+    }
+
+    #[derive(Default)]
+    struct Group<'a> {
+        children: RefCell<Vec<Pin<Rc<Rectangle2<'a>>>>>,
+    }
+    impl<'a> Group<'a> {
+        fn new() -> Pin<Rc<Self>> {
+            Rc::pin(Default::default())
+        }
+        fn add_child(&self, child: Pin<Rc<Rectangle2<'a>>>) {
+            self.children.borrow_mut().push(child);
+        }
+    }
+
+    rsml! {
+        struct Labelled : Group {
+            @alias label_width: u32 = label.width,
+            @alias label_area: u32 = label.area,
+            ;
+            Rectangle2 { height: 3 }
+            Rectangle2 { @id: label, height: 5 }
+        }
+    }
+
+    #[test]
+    fn test_rsml_alias() {
+        let l = Labelled::new();
+        assert_eq!(l.label_area().get(), 2 * 5);
+        l.label_width().set(4);
+        assert_eq!(l.children.borrow()[1].width().get(), 4);
+        assert_eq!(l.label_area().get(), 4 * 5);
+        l.label_width().set_binding(|| 7);
+        assert_eq!(l.children.borrow()[1].area().get(), 7 * 5);
+        assert_eq!(l.children.borrow()[0].area().get(), 2 * 3);
+    }
+
+    rsml! {
+        struct Square {
+            @readonly area: u32 = Square.side().get() * Square.side().get(),
+            @readonly changes: u32,
+            side: u32 = 3,
+        }
+    }
+    impl<'a> Square<'a> {
+        fn grow(&self) {
+            self.side().set(self.side().get() + 1);
+            let changes = self.changes.property();
+            changes.set(changes.get() + 1);
+        }
+    }
+
+    #[test]
+    fn test_rsml_readonly() {
+        let sq = Square::new();
+        assert_eq!(sq.area().get(), 9);
+        sq.grow();
+        assert_eq!((sq.area().get(), sq.changes().get()), (16, 1));
+        // Can still be read from a binding
+        let sq2 = sq.clone();
+        let rec = rsml! {
+            Rectangle2 {
+                width: sq2.area().get(),
+            }
+        };
+        sq.side().set(5);
+        assert_eq!(rec.width().get(), 25);
+    }
+
+    #[test]
+    #[should_panic(expected = "Rectangle2::area -> Rectangle2::height -> Rectangle2::area")]
+    fn test_rsml_binding_loop() {
+        let rec = rsml! {
+            Rectangle2 {
+                height: Rectangle2.area().value(),
             }
         };
-        assert_eq!(rec.area.value(), 3 * 2 * 2);
-        rec.width.set(8);
-        assert_eq!(rec.area.value(), 3 * 8 * 8);
+        rec.area().value();
     }
 
     /*
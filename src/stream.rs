@@ -0,0 +1,143 @@
+//! Observing properties and signals from async code.
+//!
+//! `Property::changes` and `Signal::stream` return a `Changes`, which implements
+//! `futures_core::Stream`. Like the rest of the properties, they are not thread safe: the stream
+//! must be polled on the thread owning the property, for example with a local executor.
+
+use crate::properties::{Property, ScopedConnection, Signal};
+use futures_core::Stream;
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll, Waker};
+
+struct Channel<T> {
+    queue: RefCell<VecDeque<T>>,
+    waker: RefCell<Option<Waker>>,
+    closed: Cell<bool>,
+}
+
+impl<T> Default for Channel<T> {
+    fn default() -> Self {
+        Channel {
+            queue: Default::default(),
+            waker: Default::default(),
+            closed: Default::default(),
+        }
+    }
+}
+
+impl<T> Channel<T> {
+    fn wake(&self) {
+        if let Some(w) = self.waker.borrow_mut().take() {
+            w.wake();
+        }
+    }
+}
+
+/// Owned by the callback: closes the stream when the property or the signal is dropped
+struct Sender<T>(Rc<Channel<T>>);
+impl<T> Sender<T> {
+    fn send(&self, t: T, latest_only: bool) {
+        let mut queue = self.0.queue.borrow_mut();
+        if latest_only {
+            queue.clear();
+        }
+        queue.push_back(t);
+        core::mem::drop(queue);
+        self.0.wake();
+    }
+}
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        self.0.closed.set(true);
+        self.0.wake();
+    }
+}
+
+/// A Stream of the changes of a Property, or of the emissions of a Signal.
+/// The callback is disconnected when the stream is dropped, and the stream ends when the
+/// property or the signal is dropped.
+pub struct Changes<'a, T> {
+    channel: Rc<Channel<T>>,
+    _connection: ScopedConnection<'a>,
+}
+
+impl<'a, T> Stream for Changes<'a, T> {
+    type Item = T;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<T>> {
+        if let Some(t) = self.channel.queue.borrow_mut().pop_front() {
+            return Poll::Ready(Some(t));
+        }
+        if self.channel.closed.get() {
+            return Poll::Ready(None);
+        }
+        *self.channel.waker.borrow_mut() = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl<'a, T: Clone + 'a> Property<'a, T> {
+    /// A Stream of the new values of the property, starting with the next change.
+    /// If the property changes several times before the stream is polled, only the latest
+    /// value is returned.
+    pub fn changes(self: Pin<&Self>) -> Changes<'a, T> {
+        let channel = Rc::new(Channel::default());
+        let sender = Sender(channel.clone());
+        let connection = self.on_notify(move |t| sender.send(t.clone(), true));
+        Changes {
+            channel,
+            _connection: connection.scoped(),
+        }
+    }
+}
+
+impl<'a, Args: Clone + 'a> Signal<'a, Args> {
+    /// A Stream of the arguments of the signal each time it is emitted.
+    pub fn stream(&self) -> Changes<'a, Args> {
+        let channel = Rc::new(Channel::default());
+        let sender = Sender(channel.clone());
+        let connection = self.connect(move |args| sender.send(args, false));
+        Changes {
+            channel,
+            _connection: connection.scoped(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::properties::RcProperty;
+    use futures::executor::block_on;
+    use futures::StreamExt;
+
+    #[test]
+    fn test_changes() {
+        let p = RcProperty::from(1);
+        let double = RcProperty::from_binding(|| p.get() * 2);
+        let mut changes = double.property().changes();
+        let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+        assert_eq!(changes.poll_next_unpin(&mut cx), Poll::Pending);
+        p.set(2);
+        assert_eq!(block_on(changes.next()), Some(4));
+        p.set(3);
+        p.set(4);
+        assert_eq!(block_on(changes.next()), Some(8));
+        std::mem::drop(double);
+        assert_eq!(block_on(changes.next()), None);
+    }
+
+    #[test]
+    fn test_signal_stream() {
+        let signal = Signal::<(u32, u32)>::default();
+        let mut stream = signal.stream();
+        signal.emit((1, 2));
+        signal.emit((3, 4));
+        assert_eq!(block_on(stream.next()), Some((1, 2)));
+        assert_eq!(block_on(stream.next()), Some((3, 4)));
+        std::mem::drop(signal);
+        assert_eq!(block_on(stream.next()), None);
+    }
+}